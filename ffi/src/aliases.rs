@@ -0,0 +1,81 @@
+// The aliases database is not supported by libnss, so it is implemented here in the same style
+#![allow(unsafe_code)]
+
+use libnss::interop::CBuffer;
+use libnss::interop::Response;
+use libnss::interop::ToC;
+
+/** An entry in the mail aliases database, as in /etc/aliases
+- If local is true, the alias is only valid for local mail (as for all entries from /etc/aliases) */
+#[derive(Clone)]
+pub struct Alias {
+    pub name: String,
+    pub members: Vec<String>,
+    pub local: bool,
+}
+
+impl ToC<CAlias> for Alias {
+    unsafe fn to_c(&self, result: *mut CAlias, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).name = buffer.write_str(&self.name)?;
+        (*result).members_len = self.members.len();
+        (*result).members = buffer.write_strs(&self.members)?;
+        (*result).local = self.local as libc::c_int;
+        Ok(())
+    }
+}
+
+pub trait AliasHooks {
+    fn get_all_entries() -> Response<Vec<Alias>>;
+
+    fn get_entry_by_name(name: String) -> Response<Alias>;
+}
+
+/** struct aliasent from aliases.h */
+#[repr(C)]
+pub struct CAlias {
+    pub name: *mut libc::c_char,
+    pub members_len: libc::size_t,
+    pub members: *mut *mut libc::c_char,
+    pub local: libc::c_int,
+}
+
+/** Export the aliases NSS functions for a type implementing AliasHooks */
+#[macro_export]
+macro_rules! alias_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<aliases_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+
+                use $crate::aliases::{Alias, AliasHooks, CAlias};
+
+                $crate::enumerate_hooks!(
+                    $hooks_ident,
+                    AliasHooks,
+                    Alias,
+                    CAlias,
+                    [<_nss_ $mod_ident _setaliasent>],
+                    [<_nss_ $mod_ident _getaliasent_r>],
+                    [<_nss_ $mod_ident _endaliasent>]
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getaliasbyname_r>](
+                    name: *const libc::c_char,
+                    result: *mut CAlias,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as AliasHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,93 @@
+// Enumeration functions shared by the databases which are not supported by libnss, in the same style as libnss
+#![allow(unsafe_code)]
+
+/** Export the set/get/end functions used to enumerate a database, for a type implementing its hooks
+- When enumeration starts, all entries are fetched with get_all_entries and then returned one at a time
+- This must be used once per module
+- With `h_errno`, the get function also sets h_errno, as required for some databases (like networks) */
+#[macro_export]
+macro_rules! enumerate_hooks {
+    (@iterator $hooks_ident: ident, $hooks_trait: ident, $entry: ty, $set: ident, $end: ident) => {
+        static ITERATOR: std::sync::OnceLock<std::sync::Mutex<libnss::interop::Iterator<$entry>>> =
+            std::sync::OnceLock::new();
+
+        fn iterator() -> std::sync::MutexGuard<'static, libnss::interop::Iterator<$entry>> {
+            let iterator = ITERATOR.get_or_init(|| std::sync::Mutex::new(libnss::interop::Iterator::new()));
+            iterator.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        #[no_mangle]
+        extern "C" fn $set() -> libc::c_int {
+            let mut iter = iterator();
+            let status = match <super::$hooks_ident as $hooks_trait>::get_all_entries() {
+                libnss::interop::Response::Success(entries) => iter.open(entries),
+                response => response.to_status(),
+            };
+            status as libc::c_int
+        }
+
+        #[no_mangle]
+        extern "C" fn $end() -> libc::c_int {
+            iterator().close() as libc::c_int
+        }
+    };
+    ($hooks_ident: ident, $hooks_trait: ident, $entry: ty, $c_entry: ty, $set: ident, $get: ident, $end: ident) => {
+        $crate::enumerate_hooks!(@iterator $hooks_ident, $hooks_trait, $entry, $set, $end);
+
+        #[no_mangle]
+        unsafe extern "C" fn $get(
+            result: *mut $c_entry,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+            errnop: *mut libc::c_int,
+        ) -> libc::c_int {
+            let mut iter = iterator();
+            let code = iter.next().to_c(result, buf, buflen, errnop) as libc::c_int;
+            if code == libnss::interop::NssStatus::TryAgain as libc::c_int {
+                iter.previous();
+            }
+            code
+        }
+    };
+    ($hooks_ident: ident, $hooks_trait: ident, $entry: ty, $c_entry: ty, $set: ident, $get: ident, $end: ident, h_errno) => {
+        $crate::enumerate_hooks!(@iterator $hooks_ident, $hooks_trait, $entry, $set, $end);
+
+        #[no_mangle]
+        unsafe extern "C" fn $get(
+            result: *mut $c_entry,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+            errnop: *mut libc::c_int,
+            h_errnop: *mut libc::c_int,
+        ) -> libc::c_int {
+            let mut iter = iterator();
+            let status = iter.next().to_c(result, buf, buflen, errnop);
+            if status == libnss::interop::NssStatus::TryAgain {
+                iter.previous();
+            }
+            $crate::enumerate::set_h_errno(status, errnop, h_errnop)
+        }
+    };
+}
+
+/** Set h_errno for the functions of databases which use it (like networks), then return the status code
+- If the buffer is too small, h_errno must be NETDB_INTERNAL so that the caller retries with a larger one
+
+# Safety
+errnop and h_errnop must be the pointers passed by glibc to the get function */
+pub unsafe fn set_h_errno(
+    status: libnss::interop::NssStatus,
+    errnop: *mut libc::c_int,
+    h_errnop: *mut libc::c_int,
+) -> libc::c_int {
+    use libnss::interop::NssStatus;
+    // Values from netdb.h
+    *h_errnop = match status {
+        NssStatus::Success => 0,
+        NssStatus::NotFound => 1,
+        NssStatus::TryAgain if *errnop == libc::ERANGE => -1,
+        NssStatus::TryAgain => 2,
+        _ => 3,
+    };
+    status as libc::c_int
+}
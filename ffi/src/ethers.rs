@@ -0,0 +1,111 @@
+// The ethers database is not supported by libnss, so it is implemented here in the same style
+#![allow(unsafe_code)]
+
+use libnss::interop::CBuffer;
+use libnss::interop::Response;
+use libnss::interop::ToC;
+
+/** An entry in the ethers database, as in /etc/ethers */
+#[derive(Clone)]
+pub struct Ether {
+    pub name: String,
+    pub mac: [u8; 6],
+}
+
+impl ToC<CEther> for Ether {
+    unsafe fn to_c(&self, result: *mut CEther, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).name = buffer.write_str(&self.name)?;
+        (*result).mac = self.mac;
+        Ok(())
+    }
+}
+
+pub trait EtherHooks {
+    fn get_all_entries() -> Response<Vec<Ether>>;
+
+    fn get_entry_by_name(name: String) -> Response<Ether>;
+
+    fn get_entry_by_mac(mac: [u8; 6]) -> Response<Ether>;
+}
+
+/** struct etherent used by glibc for the ethers database */
+#[repr(C)]
+pub struct CEther {
+    pub name: *mut libc::c_char,
+    pub mac: [u8; 6],
+}
+
+/** Parse a MAC address made of 6 hex bytes separated by colons, like ether_aton (e.g. 02:42:ac:11:0:2) */
+pub fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let mut bytes = [0u8; 6];
+    let mut parts = mac.split(':');
+    for byte in bytes.iter_mut() {
+        let part = parts.next()?;
+        if part.is_empty() || part.len() > 2 {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    match parts.next() {
+        Some(_) => None,
+        None => Some(bytes),
+    }
+}
+
+/** Format a MAC address as 6 lowercase hex bytes separated by colons (e.g. 02:42:ac:11:00:02) */
+pub fn mac_string(mac: &[u8; 6]) -> String {
+    mac.iter().map(|x| format!("{:02x}", x)).collect::<Vec<String>>().join(":")
+}
+
+/** Export the ethers NSS functions for a type implementing EtherHooks */
+#[macro_export]
+macro_rules! ether_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<ethers_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+
+                use $crate::ethers::{CEther, Ether, EtherHooks};
+
+                $crate::enumerate_hooks!(
+                    $hooks_ident,
+                    EtherHooks,
+                    Ether,
+                    CEther,
+                    [<_nss_ $mod_ident _setetherent>],
+                    [<_nss_ $mod_ident _getetherent_r>],
+                    [<_nss_ $mod_ident _endetherent>]
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _gethostton_r>](
+                    name: *const libc::c_char,
+                    result: *mut CEther,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as EtherHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getntohost_r>](
+                    mac: *const [u8; 6],
+                    result: *mut CEther,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    <super::$hooks_ident as EtherHooks>::get_entry_by_mac(*mac)
+                        .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,81 @@
+// The gshadow database is not supported by libnss, so it is implemented here in the same style
+#![allow(unsafe_code)]
+
+use libnss::interop::CBuffer;
+use libnss::interop::Response;
+use libnss::interop::ToC;
+
+/** An entry in the gshadow database, as in /etc/gshadow */
+#[derive(Clone)]
+pub struct GShadow {
+    pub name: String,
+    pub passwd: String,
+    pub administrators: Vec<String>,
+    pub members: Vec<String>,
+}
+
+impl ToC<CGShadow> for GShadow {
+    unsafe fn to_c(&self, result: *mut CGShadow, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).name = buffer.write_str(&self.name)?;
+        (*result).passwd = buffer.write_str(&self.passwd)?;
+        (*result).administrators = buffer.write_strs(&self.administrators)?;
+        (*result).members = buffer.write_strs(&self.members)?;
+        Ok(())
+    }
+}
+
+pub trait GShadowHooks {
+    fn get_all_entries() -> Response<Vec<GShadow>>;
+
+    fn get_entry_by_name(name: String) -> Response<GShadow>;
+}
+
+/** struct sgrp from gshadow.h */
+#[repr(C)]
+pub struct CGShadow {
+    pub name: *mut libc::c_char,
+    pub passwd: *mut libc::c_char,
+    pub administrators: *mut *mut libc::c_char,
+    pub members: *mut *mut libc::c_char,
+}
+
+/** Export the gshadow NSS functions for a type implementing GShadowHooks */
+#[macro_export]
+macro_rules! gshadow_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<gshadow_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+
+                use $crate::gshadow::{CGShadow, GShadow, GShadowHooks};
+
+                $crate::enumerate_hooks!(
+                    $hooks_ident,
+                    GShadowHooks,
+                    GShadow,
+                    CGShadow,
+                    [<_nss_ $mod_ident _setsgent>],
+                    [<_nss_ $mod_ident _getsgent_r>],
+                    [<_nss_ $mod_ident _endsgent>]
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getsgnam_r>](
+                    name: *const libc::c_char,
+                    result: *mut CGShadow,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as GShadowHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
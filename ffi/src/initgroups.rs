@@ -0,0 +1,103 @@
+// The initgroups function provided by libnss does not pass on the primary group, so it is implemented here instead
+#![allow(unsafe_code)]
+
+use std::mem;
+use std::slice;
+
+use libnss::interop::NssStatus;
+use libnss::interop::Response;
+
+/** Hooks for resolving the supplementary groups of a user, used by initgroups/getgrouplist */
+pub trait InitgroupsHooks {
+    fn get_groups_by_member(name: String, gid: libc::gid_t) -> Response<Vec<libc::gid_t>>;
+}
+
+/** Export the `_nss_<name>_initgroups_dyn` function for a type implementing InitgroupsHooks */
+#[macro_export]
+macro_rules! initgroups_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<initgroups_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::{NssStatus, Response};
+
+                use $crate::initgroups::InitgroupsHooks;
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _initgroups_dyn>](
+                    name: *const libc::c_char,
+                    skipgroup: libc::gid_t,
+                    start: *mut libc::c_long,
+                    size: *mut libc::c_long,
+                    groupsp: *mut *mut libc::gid_t,
+                    limit: libc::c_long,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    let name = match CStr::from_ptr(name).to_str() {
+                        Ok(x) => x.to_string(),
+                        Err(_) => {
+                            *errnop = libc::ENOENT;
+                            return NssStatus::NotFound as libc::c_int;
+                        }
+                    };
+                    match <super::$hooks_ident as InitgroupsHooks>::get_groups_by_member(name, skipgroup) {
+                        Response::Success(gids) => {
+                            $crate::initgroups::add_groups(gids, skipgroup, start, size, groupsp, limit, errnop)
+                                as libc::c_int
+                        }
+                        response => {
+                            *errnop = libc::ENOENT;
+                            response.to_status() as libc::c_int
+                        }
+                    }
+                }
+            }
+        }
+    };
+}
+
+/** Append gids to the array of groups being built by glibc, growing it as needed
+- The skipped group (the primary group of the user) and gids already in the array are not added again
+- If the limit is positive, no more than that many groups will be in the array
+- If the array cannot be grown, return NSS code TryAgain with errno set to ENOMEM
+
+# Safety
+The pointers must be those passed by glibc to initgroups_dyn, with the array allocated by malloc */
+pub unsafe fn add_groups(
+    gids: Vec<libc::gid_t>,
+    skipgroup: libc::gid_t,
+    start: *mut libc::c_long,
+    size: *mut libc::c_long,
+    groupsp: *mut *mut libc::gid_t,
+    limit: libc::c_long,
+    errnop: *mut libc::c_int,
+) -> NssStatus {
+    for gid in gids {
+        if gid == skipgroup || (*start > 0 && slice::from_raw_parts(*groupsp, *start as usize).contains(&gid)) {
+            continue;
+        }
+        if *start == *size {
+            if limit > 0 && *size >= limit {
+                break;
+            }
+            let mut new_size = (*size * 2).max(16);
+            if limit > 0 {
+                new_size = new_size.min(limit);
+            }
+            let new_groups = libc::realloc(
+                *groupsp as *mut libc::c_void,
+                new_size as usize * mem::size_of::<libc::gid_t>(),
+            ) as *mut libc::gid_t;
+            if new_groups.is_null() {
+                *errnop = libc::ENOMEM;
+                return NssStatus::TryAgain;
+            }
+            *groupsp = new_groups;
+            *size = new_size;
+        }
+        *(*groupsp).add(*start as usize) = gid;
+        *start += 1;
+    }
+    NssStatus::Success
+}
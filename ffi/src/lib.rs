@@ -0,0 +1,32 @@
+// C interfaces exported by libnss_shim which libnss does not provide, such as the gshadow, initgroups, netgroup, and
+// subid functions, and paged enumeration, along with the file locks used by libnss_shim
+// They are kept in this crate so that libnss_shim itself can forbid unsafe code, as its macros are used from there in
+// the same way as the macros of libnss
+// Unsafe code is only allowed in the modules which opt in, to convert entries to C types and to export C functions
+#![deny(unsafe_code)]
+
+use libnss::interop::Response;
+
+pub mod aliases;
+pub mod enumerate;
+pub mod ethers;
+pub mod gshadow;
+pub mod initgroups;
+pub mod lock;
+pub mod netgroup;
+pub mod networks;
+pub mod paged;
+pub mod protocols;
+pub mod rpc;
+pub mod services;
+pub mod subid;
+
+/** Convert a Response which is not Success to a different type */
+pub fn failure<T, U>(response: Response<U>) -> Response<T> {
+    match response {
+        Response::NotFound => Response::NotFound,
+        Response::Unavail => Response::Unavail,
+        Response::Return => Response::Return,
+        _ => Response::TryAgain,
+    }
+}
@@ -0,0 +1,21 @@
+// Advisory file locks, used by libnss_shim to serialize updates of files shared between processes
+#![allow(unsafe_code)]
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/** Take an exclusive advisory lock (flock) on a file, waiting until it is available
+- The lock is released when the file is closed
+- If the lock cannot be taken, return the OS error */
+pub fn lock_exclusive(file: &File) -> io::Result<()> {
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
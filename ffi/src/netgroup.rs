@@ -0,0 +1,231 @@
+// The netgroup database is not supported by libnss, so it is implemented here in the same style
+#![allow(unsafe_code)]
+
+use std::slice;
+
+use libnss::interop::CBuffer;
+use libnss::interop::NssStatus;
+use libnss::interop::Response;
+
+/** A member of a netgroup, as in /etc/netgroup
+- A triple matches any host/user/domain for a field which is None
+- A nested netgroup is handed back to glibc, which looks it up through every source in /etc/nsswitch.conf */
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetgroupEntry {
+    Triple {
+        host: Option<String>,
+        user: Option<String>,
+        domain: Option<String>,
+    },
+    Group(String),
+}
+
+pub trait NetgroupHooks {
+    fn get_entry_by_name(name: String) -> Response<Vec<NetgroupEntry>>;
+}
+
+/** Parse the members of a netgroup in /etc/netgroup format, e.g. `(host1,alice,example.com) (host2,,) othergroup`
+- Whitespace around each field of a triple is ignored, and a blank field is None
+- If a triple is not closed or does not have 3 fields, return None */
+pub fn parse_members(members: &str) -> Option<Vec<NetgroupEntry>> {
+    let field = |x: &str| match x.trim() {
+        "" => None,
+        x => Some(x.to_string()),
+    };
+    let mut entries = Vec::new();
+    let mut rest = members.trim_start();
+    while !rest.is_empty() {
+        match rest.strip_prefix('(') {
+            Some(triple) => {
+                let end = triple.find(')')?;
+                let fields: Vec<&str> = triple[..end].split(',').collect();
+                if fields.len() != 3 {
+                    return None;
+                }
+                entries.push(NetgroupEntry::Triple {
+                    host: field(fields[0]),
+                    user: field(fields[1]),
+                    domain: field(fields[2]),
+                });
+                rest = triple[end + 1..].trim_start();
+            }
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                entries.push(NetgroupEntry::Group(rest[..end].to_string()));
+                rest = rest[end..].trim_start();
+            }
+        }
+    }
+    Some(entries)
+}
+
+/** The start of struct __netgrent from glibc's netgroup.h, which holds the state of a netgroup lookup
+- The remaining fields are only used by glibc, so they are not declared here */
+#[repr(C)]
+pub struct CNetgroup {
+    // enum { triple_val, group_val }
+    pub kind: libc::c_int,
+    // union of a host/user/domain triple and a group name (the first pointer)
+    pub val: [*const libc::c_char; 3],
+    pub data: *mut libc::c_char,
+    pub data_size: libc::size_t,
+    // union of a cursor and a position
+    pub position: libc::c_ulong,
+    pub first: libc::c_int,
+}
+
+const TRIPLE_VAL: libc::c_int = 0;
+const GROUP_VAL: libc::c_int = 1;
+
+/** Serialize the members of a netgroup, so they can be stored by glibc until each one is returned
+- Each member is 4 NUL-terminated strings: `t` and the triple, or `g`, the netgroup name, and two blanks
+- A blank field in a triple means it matches anything */
+fn serialize(entries: &[NetgroupEntry]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for entry in entries {
+        let fields = match entry {
+            NetgroupEntry::Triple { host, user, domain } => [
+                "t",
+                host.as_deref().unwrap_or(""),
+                user.as_deref().unwrap_or(""),
+                domain.as_deref().unwrap_or(""),
+            ],
+            NetgroupEntry::Group(name) => ["g", name, "", ""],
+        };
+        for field in fields {
+            data.extend_from_slice(field.as_bytes());
+            data.push(0);
+        }
+    }
+    data
+}
+
+/** Store the members of a netgroup in the lookup state, to be returned by next_entry
+- If there are no members, return NSS code NotFound
+
+# Safety
+result must be the lookup state passed by glibc to setnetgrent */
+pub unsafe fn start(entries: Vec<NetgroupEntry>, result: *mut CNetgroup) -> NssStatus {
+    (*result).data = std::ptr::null_mut();
+    (*result).data_size = 0;
+    (*result).position = 0;
+    if entries.is_empty() {
+        return NssStatus::NotFound;
+    }
+    let data = serialize(&entries);
+    let ptr = libc::malloc(data.len()) as *mut libc::c_char;
+    if ptr.is_null() {
+        return NssStatus::TryAgain;
+    }
+    libc::memcpy(ptr as *mut libc::c_void, data.as_ptr() as *const libc::c_void, data.len());
+    (*result).data = ptr;
+    (*result).data_size = data.len();
+    NssStatus::Success
+}
+
+/** Return the next member of a netgroup, copying its strings into the buffer
+- After the last member, return NSS code Return so that glibc moves on to any nested netgroups
+- If the buffer is too small, return NSS code TryAgain with errno set to ERANGE
+
+# Safety
+The pointers must be those passed by glibc to getnetgrent_r, with the lookup state set up by start */
+pub unsafe fn next_entry(
+    result: *mut CNetgroup,
+    buf: *mut libc::c_char,
+    buflen: libc::size_t,
+    errnop: *mut libc::c_int,
+) -> NssStatus {
+    if (*result).data.is_null() {
+        return NssStatus::NotFound;
+    }
+    let data = slice::from_raw_parts((*result).data as *const u8, (*result).data_size);
+    let position = (*result).position as usize;
+    if position >= data.len() {
+        return NssStatus::Return;
+    }
+    let fields: Vec<&[u8]> = data[position..].split(|x| *x == 0).take(4).collect();
+    if fields.len() < 4 {
+        return NssStatus::Unavail;
+    }
+    let mut buffer = CBuffer::new(buf as *mut libc::c_void, buflen);
+    buffer.clear();
+    let mut val: [*const libc::c_char; 3] = [std::ptr::null(); 3];
+    for (i, field) in fields[1..].iter().enumerate() {
+        if field.is_empty() {
+            continue;
+        }
+        val[i] = match buffer.write_str(&String::from_utf8_lossy(field)) {
+            Ok(ptr) => ptr,
+            Err(_) => {
+                *errnop = libc::ERANGE;
+                return NssStatus::TryAgain;
+            }
+        };
+    }
+    (*result).kind = if fields[0] == b"g" { GROUP_VAL } else { TRIPLE_VAL };
+    (*result).val = val;
+    (*result).position = (position + fields.iter().map(|x| x.len() + 1).sum::<usize>()) as libc::c_ulong;
+    NssStatus::Success
+}
+
+/** Free the members of a netgroup stored by start
+
+# Safety
+result must be the lookup state passed by glibc to endnetgrent, set up by start */
+pub unsafe fn end(result: *mut CNetgroup) -> NssStatus {
+    libc::free((*result).data as *mut libc::c_void);
+    (*result).data = std::ptr::null_mut();
+    (*result).data_size = 0;
+    (*result).position = 0;
+    NssStatus::Success
+}
+
+/** Export the netgroup NSS functions for a type implementing NetgroupHooks
+- glibc implements innetgr using these functions, so it does not need its own */
+#[macro_export]
+macro_rules! netgroup_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<netgroup_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+
+                use $crate::netgroup::{CNetgroup, NetgroupHooks};
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _setnetgrent>](
+                    group: *const libc::c_char,
+                    result: *mut CNetgroup,
+                ) -> libc::c_int {
+                    let response = match CStr::from_ptr(group).to_str() {
+                        Ok(name) => <super::$hooks_ident as NetgroupHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    };
+                    match response {
+                        Response::Success(entries) => $crate::netgroup::start(entries, result) as libc::c_int,
+                        response => {
+                            (*result).data = std::ptr::null_mut();
+                            response.to_status() as libc::c_int
+                        }
+                    }
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getnetgrent_r>](
+                    result: *mut CNetgroup,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    $crate::netgroup::next_entry(result, buf, buflen, errnop) as libc::c_int
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _endnetgrent>](result: *mut CNetgroup) -> libc::c_int {
+                    $crate::netgroup::end(result) as libc::c_int
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,127 @@
+// The networks database is not supported by libnss, so it is implemented here in the same style
+#![allow(unsafe_code)]
+
+use libnss::interop::CBuffer;
+use libnss::interop::Response;
+use libnss::interop::ToC;
+
+/** An entry in the networks database, as in /etc/networks
+- The network number is in host byte order, as returned by inet_network (e.g. 10.1 is 0x0a01) */
+#[derive(Clone)]
+pub struct Network {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub net: u32,
+}
+
+impl ToC<CNetwork> for Network {
+    unsafe fn to_c(&self, result: *mut CNetwork, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).name = buffer.write_str(&self.name)?;
+        (*result).aliases = buffer.write_strs(&self.aliases)?;
+        (*result).addrtype = libc::AF_INET;
+        (*result).net = self.net;
+        Ok(())
+    }
+}
+
+pub trait NetworkHooks {
+    fn get_all_entries() -> Response<Vec<Network>>;
+
+    fn get_entry_by_name(name: String) -> Response<Network>;
+
+    fn get_entry_by_addr(net: u32) -> Response<Network>;
+}
+
+/** struct netent from netdb.h */
+#[repr(C)]
+pub struct CNetwork {
+    pub name: *mut libc::c_char,
+    pub aliases: *mut *mut libc::c_char,
+    pub addrtype: libc::c_int,
+    pub net: u32,
+}
+
+/** Parse a network number in the dotted form used by /etc/networks, in the same way as inet_network
+- Each of the up to 4 parts is a byte, so 10, 10.1, and 10.1.0.0 are 0x0a, 0x0a01, and 0x0a010000 respectively */
+pub fn parse_network(network: &str) -> Option<u32> {
+    let parts: Vec<&str> = network.split('.').collect();
+    if parts.len() > 4 {
+        return None;
+    }
+    let mut net: u32 = 0;
+    for part in parts {
+        net = (net << 8) | part.parse::<u8>().ok()? as u32;
+    }
+    Some(net)
+}
+
+/** Format a network number in dotted form, using as few parts as needed (the reverse of parse_network) */
+pub fn network_string(net: u32) -> String {
+    let bytes = net.to_be_bytes();
+    let start = bytes.iter().position(|x| *x != 0).unwrap_or(3);
+    bytes[start..].iter().map(|x| x.to_string()).collect::<Vec<String>>().join(".")
+}
+
+/** Export the networks NSS functions for a type implementing NetworkHooks */
+#[macro_export]
+macro_rules! network_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<networks_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+
+                use $crate::enumerate::set_h_errno;
+                use $crate::networks::{CNetwork, Network, NetworkHooks};
+
+                $crate::enumerate_hooks!(
+                    $hooks_ident,
+                    NetworkHooks,
+                    Network,
+                    CNetwork,
+                    [<_nss_ $mod_ident _setnetent>],
+                    [<_nss_ $mod_ident _getnetent_r>],
+                    [<_nss_ $mod_ident _endnetent>],
+                    h_errno
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getnetbyname_r>](
+                    name: *const libc::c_char,
+                    result: *mut CNetwork,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                    h_errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    let status = match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as NetworkHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop);
+                    set_h_errno(status, errnop, h_errnop)
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getnetbyaddr_r>](
+                    net: u32,
+                    addrtype: libc::c_int,
+                    result: *mut CNetwork,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                    h_errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    // Only IPv4 networks are supported
+                    let status = match addrtype {
+                        libc::AF_INET | libc::AF_UNSPEC => <super::$hooks_ident as NetworkHooks>::get_entry_by_addr(net),
+                        _ => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop);
+                    set_h_errno(status, errnop, h_errnop)
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,306 @@
+// Paged enumeration of the passwd, group, and shadow databases
+// The functions exported by libnss for those databases hold every entry in memory, so they are replaced here
+#![allow(unsafe_code)]
+
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use libnss::interop::NssStatus;
+use libnss::interop::Response;
+
+use crate::failure;
+
+/** The position of a page in a paged get_all_entries command
+- The offset is the number of entries in the pages before it, and the cursor is given by the previous page (if any)
+- Listed is the number of those entries which were not hidden */
+#[derive(Clone, Default)]
+pub struct Position {
+    pub offset: usize,
+    pub cursor: String,
+    pub listed: usize,
+}
+
+/** A page of entries, with the position of the next page if there is one
+- Hidden has the positions of entries which are hidden from enumeration, if they were not left out of the page */
+pub struct Page<T> {
+    pub entries: Vec<T>,
+    pub hidden: HashSet<usize>,
+    pub next: Option<Position>,
+}
+
+pub trait PagedHooks<T> {
+    fn get_page(position: Position) -> Response<Page<T>>;
+}
+
+/** Fetch every page and combine their entries, as needed when building index files or deriving lookups
+- The positions of hidden entries are combined in the same way */
+pub fn collect_pages<T>(fetch: impl Fn(Position) -> Response<Page<T>>) -> Response<(Vec<T>, HashSet<usize>)> {
+    let mut entries = Vec::new();
+    let mut hidden = HashSet::new();
+    let mut position = Some(Position::default());
+    while let Some(current) = position {
+        match fetch(current) {
+            Response::Success(page) => {
+                hidden.extend(page.hidden.into_iter().map(|x| x + entries.len()));
+                entries.extend(page.entries);
+                position = page.next;
+            }
+            response => return failure(response),
+        }
+    }
+    Response::Success((entries, hidden))
+}
+
+/** Holds the current page during enumeration, fetching the next page when it runs out
+- This is the paged equivalent of libnss::interop::Iterator */
+pub struct PagedIterator<T> {
+    items: Option<VecDeque<T>>,
+    next: Option<Position>,
+}
+
+impl<T> Default for PagedIterator<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> PagedIterator<T> {
+    pub fn new() -> Self {
+        PagedIterator {items: None, next: None}
+    }
+
+    pub fn open(&mut self, page: Page<T>) -> NssStatus {
+        self.items = Some(VecDeque::from(page.entries));
+        self.next = page.next;
+        NssStatus::Success
+    }
+
+    /** Return the next entry, fetching the next page if the current one has been returned
+    - If fetching fails, the same page is fetched again on the next call */
+    pub fn next(&mut self, fetch: impl Fn(Position) -> Response<Page<T>>) -> Response<T> {
+        loop {
+            let items = match self.items {
+                Some(ref mut items) => items,
+                None => return Response::Unavail,
+            };
+            if let Some(entry) = items.pop_front() {
+                return Response::Success(entry);
+            }
+            let position = match self.next.take() {
+                Some(position) => position,
+                None => return Response::NotFound,
+            };
+            match fetch(position.clone()) {
+                Response::Success(page) => {
+                    items.extend(page.entries);
+                    self.next = page.next;
+                }
+                response => {
+                    self.next = Some(position);
+                    return failure(response);
+                }
+            }
+        }
+    }
+
+    /** Put back an entry which could not be returned (e.g. because the buffer was too small) */
+    pub fn previous(&mut self, entry: T) {
+        if let Some(ref mut items) = self.items {
+            items.push_front(entry);
+        }
+    }
+
+    pub fn close(&mut self) -> NssStatus {
+        self.items = None;
+        self.next = None;
+        NssStatus::Success
+    }
+}
+
+/** Export the set/get/end functions used to enumerate a database page by page, for a type implementing PagedHooks
+- This must be used once per module */
+#[macro_export]
+macro_rules! paged_enumerate_hooks {
+    ($hooks_ident: ident, $entry: ty, $c_entry: ty, $set: ident, $get: ident, $end: ident) => {
+        static ITERATOR: std::sync::OnceLock<std::sync::Mutex<$crate::paged::PagedIterator<$entry>>> =
+            std::sync::OnceLock::new();
+
+        fn iterator() -> std::sync::MutexGuard<'static, $crate::paged::PagedIterator<$entry>> {
+            let iterator = ITERATOR.get_or_init(|| std::sync::Mutex::new($crate::paged::PagedIterator::new()));
+            iterator.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        #[no_mangle]
+        extern "C" fn $set() -> libc::c_int {
+            let mut iter = iterator();
+            let position = $crate::paged::Position::default();
+            let status = match <super::$hooks_ident as $crate::paged::PagedHooks<$entry>>::get_page(position) {
+                libnss::interop::Response::Success(page) => iter.open(page),
+                response => response.to_status(),
+            };
+            status as libc::c_int
+        }
+
+        #[no_mangle]
+        unsafe extern "C" fn $get(
+            result: *mut $c_entry,
+            buf: *mut libc::c_char,
+            buflen: libc::size_t,
+            errnop: *mut libc::c_int,
+        ) -> libc::c_int {
+            let mut iter = iterator();
+            let response = iter.next(<super::$hooks_ident as $crate::paged::PagedHooks<$entry>>::get_page);
+            let status = response.to_c(result, buf, buflen, errnop);
+            if let (libnss::interop::NssStatus::TryAgain, libnss::interop::Response::Success(entry)) = (status, response) {
+                iter.previous(entry);
+            }
+            status as libc::c_int
+        }
+
+        #[no_mangle]
+        extern "C" fn $end() -> libc::c_int {
+            iterator().close() as libc::c_int
+        }
+    };
+}
+
+/** Export the passwd NSS functions for a type implementing PasswdHooks and PagedHooks, instead of libnss_passwd_hooks */
+#[macro_export]
+macro_rules! passwd_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<passwd_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+                use libnss::passwd::{CPasswd, Passwd, PasswdHooks};
+
+                $crate::paged_enumerate_hooks!(
+                    $hooks_ident,
+                    Passwd,
+                    CPasswd,
+                    [<_nss_ $mod_ident _setpwent>],
+                    [<_nss_ $mod_ident _getpwent_r>],
+                    [<_nss_ $mod_ident _endpwent>]
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getpwuid_r>](
+                    uid: libc::uid_t,
+                    result: *mut CPasswd,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    <super::$hooks_ident as PasswdHooks>::get_entry_by_uid(uid)
+                        .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getpwnam_r>](
+                    name: *const libc::c_char,
+                    result: *mut CPasswd,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as PasswdHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
+
+/** Export the group NSS functions for a type implementing GroupHooks and PagedHooks, instead of libnss_group_hooks */
+#[macro_export]
+macro_rules! group_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<group_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::group::{CGroup, Group, GroupHooks};
+                use libnss::interop::Response;
+
+                $crate::paged_enumerate_hooks!(
+                    $hooks_ident,
+                    Group,
+                    CGroup,
+                    [<_nss_ $mod_ident _setgrent>],
+                    [<_nss_ $mod_ident _getgrent_r>],
+                    [<_nss_ $mod_ident _endgrent>]
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getgrgid_r>](
+                    gid: libc::gid_t,
+                    result: *mut CGroup,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    <super::$hooks_ident as GroupHooks>::get_entry_by_gid(gid)
+                        .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getgrnam_r>](
+                    name: *const libc::c_char,
+                    result: *mut CGroup,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as GroupHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
+
+/** Export the shadow NSS functions for a type implementing ShadowHooks and PagedHooks, instead of libnss_shadow_hooks */
+#[macro_export]
+macro_rules! shadow_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<shadow_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+                use libnss::shadow::{CShadow, Shadow, ShadowHooks};
+
+                $crate::paged_enumerate_hooks!(
+                    $hooks_ident,
+                    Shadow,
+                    CShadow,
+                    [<_nss_ $mod_ident _setspent>],
+                    [<_nss_ $mod_ident _getspent_r>],
+                    [<_nss_ $mod_ident _endspent>]
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getspnam_r>](
+                    name: *const libc::c_char,
+                    result: *mut CShadow,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as ShadowHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,92 @@
+// The protocols database is not supported by libnss, so it is implemented here in the same style
+#![allow(unsafe_code)]
+
+use libnss::interop::CBuffer;
+use libnss::interop::Response;
+use libnss::interop::ToC;
+
+/** An entry in the protocols database, as in /etc/protocols */
+#[derive(Clone)]
+pub struct Protocol {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub number: i32,
+}
+
+impl ToC<CProtocol> for Protocol {
+    unsafe fn to_c(&self, result: *mut CProtocol, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).name = buffer.write_str(&self.name)?;
+        (*result).aliases = buffer.write_strs(&self.aliases)?;
+        (*result).number = self.number as libc::c_int;
+        Ok(())
+    }
+}
+
+pub trait ProtocolHooks {
+    fn get_all_entries() -> Response<Vec<Protocol>>;
+
+    fn get_entry_by_name(name: String) -> Response<Protocol>;
+
+    fn get_entry_by_number(number: i32) -> Response<Protocol>;
+}
+
+/** struct protoent from netdb.h */
+#[repr(C)]
+pub struct CProtocol {
+    pub name: *mut libc::c_char,
+    pub aliases: *mut *mut libc::c_char,
+    pub number: libc::c_int,
+}
+
+/** Export the protocols NSS functions for a type implementing ProtocolHooks */
+#[macro_export]
+macro_rules! protocol_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<protocols_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+
+                use $crate::protocols::{CProtocol, Protocol, ProtocolHooks};
+
+                $crate::enumerate_hooks!(
+                    $hooks_ident,
+                    ProtocolHooks,
+                    Protocol,
+                    CProtocol,
+                    [<_nss_ $mod_ident _setprotoent>],
+                    [<_nss_ $mod_ident _getprotoent_r>],
+                    [<_nss_ $mod_ident _endprotoent>]
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getprotobyname_r>](
+                    name: *const libc::c_char,
+                    result: *mut CProtocol,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as ProtocolHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getprotobynumber_r>](
+                    number: libc::c_int,
+                    result: *mut CProtocol,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    <super::$hooks_ident as ProtocolHooks>::get_entry_by_number(number)
+                        .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,92 @@
+// The rpc database is not supported by libnss, so it is implemented here in the same style
+#![allow(unsafe_code)]
+
+use libnss::interop::CBuffer;
+use libnss::interop::Response;
+use libnss::interop::ToC;
+
+/** An entry in the rpc database, as in /etc/rpc */
+#[derive(Clone)]
+pub struct Rpc {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub number: i32,
+}
+
+impl ToC<CRpc> for Rpc {
+    unsafe fn to_c(&self, result: *mut CRpc, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).name = buffer.write_str(&self.name)?;
+        (*result).aliases = buffer.write_strs(&self.aliases)?;
+        (*result).number = self.number as libc::c_int;
+        Ok(())
+    }
+}
+
+pub trait RpcHooks {
+    fn get_all_entries() -> Response<Vec<Rpc>>;
+
+    fn get_entry_by_name(name: String) -> Response<Rpc>;
+
+    fn get_entry_by_number(number: i32) -> Response<Rpc>;
+}
+
+/** struct rpcent from rpc/netdb.h */
+#[repr(C)]
+pub struct CRpc {
+    pub name: *mut libc::c_char,
+    pub aliases: *mut *mut libc::c_char,
+    pub number: libc::c_int,
+}
+
+/** Export the rpc NSS functions for a type implementing RpcHooks */
+#[macro_export]
+macro_rules! rpc_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<rpc_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+
+                use $crate::rpc::{CRpc, Rpc, RpcHooks};
+
+                $crate::enumerate_hooks!(
+                    $hooks_ident,
+                    RpcHooks,
+                    Rpc,
+                    CRpc,
+                    [<_nss_ $mod_ident _setrpcent>],
+                    [<_nss_ $mod_ident _getrpcent_r>],
+                    [<_nss_ $mod_ident _endrpcent>]
+                );
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getrpcbyname_r>](
+                    name: *const libc::c_char,
+                    result: *mut CRpc,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match CStr::from_ptr(name).to_str() {
+                        Ok(name) => <super::$hooks_ident as RpcHooks>::get_entry_by_name(name.to_string()),
+                        Err(_) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getrpcbynumber_r>](
+                    number: libc::c_int,
+                    result: *mut CRpc,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    <super::$hooks_ident as RpcHooks>::get_entry_by_number(number)
+                        .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,118 @@
+// The services database is not supported by libnss, so it is implemented here in the same style
+#![allow(unsafe_code)]
+
+use libnss::interop::CBuffer;
+use libnss::interop::Response;
+use libnss::interop::ToC;
+
+/** An entry in the services database, as in /etc/services */
+#[derive(Clone)]
+pub struct Service {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub port: u16,
+    pub proto: String,
+}
+
+impl ToC<CService> for Service {
+    unsafe fn to_c(&self, result: *mut CService, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).name = buffer.write_str(&self.name)?;
+        (*result).aliases = buffer.write_strs(&self.aliases)?;
+        // The port is stored in network byte order
+        (*result).port = self.port.to_be() as libc::c_int;
+        (*result).proto = buffer.write_str(&self.proto)?;
+        Ok(())
+    }
+}
+
+/** Hooks for the services database
+- The protocol is None if any protocol can match */
+pub trait ServiceHooks {
+    fn get_all_entries() -> Response<Vec<Service>>;
+
+    fn get_entry_by_name(name: String, proto: Option<String>) -> Response<Service>;
+
+    fn get_entry_by_port(port: u16, proto: Option<String>) -> Response<Service>;
+}
+
+/** struct servent from netdb.h */
+#[repr(C)]
+pub struct CService {
+    pub name: *mut libc::c_char,
+    pub aliases: *mut *mut libc::c_char,
+    pub port: libc::c_int,
+    pub proto: *mut libc::c_char,
+}
+
+/** Export the services NSS functions for a type implementing ServiceHooks */
+#[macro_export]
+macro_rules! service_hooks {
+    ($mod_ident: ident, $hooks_ident: ident) => {
+        paste::paste! {
+            mod [<services_ $mod_ident _hooks_impl>] {
+                use std::ffi::CStr;
+
+                use libnss::interop::Response;
+
+                use $crate::services::{CService, Service, ServiceHooks};
+
+                $crate::enumerate_hooks!(
+                    $hooks_ident,
+                    ServiceHooks,
+                    Service,
+                    CService,
+                    [<_nss_ $mod_ident _setservent>],
+                    [<_nss_ $mod_ident _getservent_r>],
+                    [<_nss_ $mod_ident _endservent>]
+                );
+
+                /** Convert the optional protocol given to a lookup, which is NULL if any protocol can match */
+                unsafe fn proto(proto: *const libc::c_char) -> Result<Option<String>, ()> {
+                    if proto.is_null() {
+                        return Ok(None);
+                    }
+                    match CStr::from_ptr(proto).to_str() {
+                        Ok(proto) => Ok(Some(proto.to_string())),
+                        Err(_) => Err(()),
+                    }
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getservbyname_r>](
+                    name: *const libc::c_char,
+                    protocol: *const libc::c_char,
+                    result: *mut CService,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    match (CStr::from_ptr(name).to_str(), proto(protocol)) {
+                        (Ok(name), Ok(proto)) => {
+                            <super::$hooks_ident as ServiceHooks>::get_entry_by_name(name.to_string(), proto)
+                        }
+                        _ => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+
+                #[no_mangle]
+                unsafe extern "C" fn [<_nss_ $mod_ident _getservbyport_r>](
+                    port: libc::c_int,
+                    protocol: *const libc::c_char,
+                    result: *mut CService,
+                    buf: *mut libc::c_char,
+                    buflen: libc::size_t,
+                    errnop: *mut libc::c_int,
+                ) -> libc::c_int {
+                    // The port is given in network byte order
+                    let port = u16::from_be(port as u16);
+                    match proto(protocol) {
+                        Ok(proto) => <super::$hooks_ident as ServiceHooks>::get_entry_by_port(port, proto),
+                        Err(()) => Response::NotFound,
+                    }
+                    .to_c(result, buf, buflen, errnop) as libc::c_int
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,382 @@
+// Subordinate id ranges are provided to shadow-utils as a libsubid plugin rather than through NSS
+#![allow(unsafe_code)]
+
+use std::ffi::CStr;
+
+use libnss::interop::Response;
+
+/** The type of a subordinate id range, as in /etc/subuid or /etc/subgid */
+#[derive(Clone, Copy, PartialEq)]
+pub enum SubidKind {
+    Uid,
+    Gid,
+}
+
+impl SubidKind {
+    /** Convert enum subid_type from subid.h
+    - If the type is unknown, return None */
+    pub fn from_c(kind: libc::c_int) -> Option<SubidKind> {
+        match kind {
+            1 => Some(SubidKind::Uid),
+            2 => Some(SubidKind::Gid),
+            _ => None,
+        }
+    }
+
+    /** The value of the `<$kind>` code for commands */
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubidKind::Uid => "uid",
+            SubidKind::Gid => "gid",
+        }
+    }
+}
+
+/** A range of subordinate ids belonging to an owner, as in /etc/subuid
+- The owner can be a user name or a uid */
+#[derive(Clone)]
+pub struct SubidRange {
+    pub owner: String,
+    pub start: u64,
+    pub count: u64,
+}
+
+impl SubidRange {
+    /** Determine if an id is in the range */
+    pub fn contains(&self, id: u64) -> bool {
+        id >= self.start && id - self.start < self.count
+    }
+}
+
+pub trait SubidHooks {
+    fn get_all_entries(kind: SubidKind) -> Response<Vec<SubidRange>>;
+
+    fn get_ranges_by_owner(owner: String, kind: SubidKind) -> Response<Vec<SubidRange>>;
+
+    fn get_owners_by_id(id: u64, kind: SubidKind) -> Response<Vec<SubidRange>>;
+}
+
+/** struct subid_range from subid.h */
+#[repr(C)]
+pub struct CSubidRange {
+    pub start: libc::c_ulong,
+    pub count: libc::c_ulong,
+}
+
+// Values of enum subid_status from subid.h
+pub const SUBID_STATUS_SUCCESS: libc::c_int = 0;
+pub const SUBID_STATUS_UNKNOWN_USER: libc::c_int = 1;
+pub const SUBID_STATUS_ERROR_CONN: libc::c_int = 2;
+pub const SUBID_STATUS_ERROR: libc::c_int = 3;
+
+/** Check that ranges are valid, so that no subordinate id is given to more than one owner
+- Each range must have a count of at least 1 and end at or below the largest possible id
+- Ranges must not overlap, whether they have the same owner or not
+- If invalid, return Err with a description of the problem */
+pub fn validate_ranges(ranges: &[SubidRange]) -> Result<(), String> {
+    let mut sorted: Vec<&SubidRange> = ranges.iter().collect();
+    sorted.sort_by_key(|x| x.start);
+    let mut previous: Option<&SubidRange> = None;
+    for range in sorted {
+        let end = range.start.checked_add(range.count).unwrap_or(u64::MAX);
+        if range.count == 0 || end > u32::MAX as u64 + 1 {
+            return Err(format!(
+                "Range {}:{}:{} is empty or exceeds the largest possible id",
+                range.owner, range.start, range.count
+            ));
+        }
+        if let Some(previous) = previous {
+            if range.start < previous.start + previous.count {
+                return Err(format!(
+                    "Range {}:{}:{} overlaps range {}:{}:{}",
+                    range.owner, range.start, range.count, previous.owner, previous.start, previous.count
+                ));
+            }
+        }
+        previous = Some(range);
+    }
+    Ok(())
+}
+
+// Buffer sizes for getpwnam_r, which is retried with double the buffer while it is too small, up to the largest
+const PASSWD_BUFFER_SIZE: usize = 16384;
+const PASSWD_BUFFER_MAX: usize = 1048576;
+
+/** Find the uid of an owner, which can be a user name or a uid
+- The name is resolved through NSS, so owners from any source in /etc/nsswitch.conf can be found
+- If the owner does not exist, or its entry does not fit in the largest buffer, return None */
+pub fn owner_uid(owner: &str) -> Option<libc::uid_t> {
+    if let Ok(uid) = owner.parse::<libc::uid_t>() {
+        return Some(uid);
+    }
+    let name = std::ffi::CString::new(owner).ok()?;
+    let mut size = PASSWD_BUFFER_SIZE;
+    loop {
+        let mut buffer = vec![0 as libc::c_char; size];
+        unsafe {
+            let mut passwd: libc::passwd = std::mem::zeroed();
+            let mut result: *mut libc::passwd = std::ptr::null_mut();
+            let code = libc::getpwnam_r(name.as_ptr(), &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result);
+            match code {
+                0 if !result.is_null() => return Some(passwd.pw_uid),
+                libc::ERANGE if size < PASSWD_BUFFER_MAX => size *= 2,
+                _ => return None,
+            }
+        }
+    }
+}
+
+/** Find the uid of an owner to match ranges against with owner_matches
+- It is only looked up if a range is owned by a uid, since ranges owned by a name are matched by the name alone
+- If it is not needed or the owner does not exist, return None */
+pub fn matching_uid(ranges: &[SubidRange], owner: &str) -> Option<libc::uid_t> {
+    match ranges.iter().any(|x| x.owner.parse::<libc::uid_t>().is_ok()) {
+        true => owner_uid(owner),
+        false => None,
+    }
+}
+
+/** Determine if a range belongs to an owner, by name or by uid
+- The uid of the owner is given by matching_uid, so that it is only looked up once for all the ranges */
+pub fn owner_matches(range: &SubidRange, owner: &str, uid: Option<libc::uid_t>) -> bool {
+    range.owner == owner || (uid.is_some() && range.owner.parse::<libc::uid_t>().ok() == uid)
+}
+
+/** Determine if the ranges of an owner cover every id from start to start + count, possibly across adjacent ranges */
+pub fn covers(ranges: &[SubidRange], start: u64, count: u64) -> bool {
+    let mut next = start;
+    let end = start.saturating_add(count);
+    while next < end {
+        match ranges.iter().find(|x| x.contains(next)) {
+            Some(range) => next = range.start + range.count,
+            None => return false,
+        }
+    }
+    true
+}
+
+/** Convert the status of a Response which is not Success to enum subid_status */
+pub fn failure_status<T>(response: Response<T>) -> libc::c_int {
+    match response {
+        Response::Success(_) => SUBID_STATUS_SUCCESS,
+        Response::Unavail => SUBID_STATUS_ERROR_CONN,
+        _ => SUBID_STATUS_ERROR,
+    }
+}
+
+/** Copy items to an array allocated with malloc, which the caller frees with shadow_subid_free
+- If there are no items, the array is NULL
+- If the allocation fails, return None
+
+# Safety
+The array must only be freed with free, and T must not need to be dropped */
+pub unsafe fn malloc_array<T>(items: Vec<T>) -> Option<*mut T> {
+    if items.is_empty() {
+        return Some(std::ptr::null_mut());
+    }
+    let array = libc::malloc(items.len() * std::mem::size_of::<T>()) as *mut T;
+    if array.is_null() {
+        return None;
+    }
+    for (i, item) in items.into_iter().enumerate() {
+        array.add(i).write(item);
+    }
+    Some(array)
+}
+
+/** Read the owner passed by libsubid
+- If the owner is NULL or not valid UTF-8, return None
+
+# Safety
+owner must be NULL or point to a NUL-terminated string */
+pub unsafe fn owner_str(owner: *const libc::c_char) -> Option<String> {
+    if owner.is_null() {
+        return None;
+    }
+    CStr::from_ptr(owner).to_str().ok().map(|x| x.to_string())
+}
+
+/** Export the libsubid plugin functions for a type implementing SubidHooks
+- shadow-utils loads the plugin as libsubid_<module>.so when /etc/nsswitch.conf has `subid: <module>`
+- Owners with no ranges are not an error, so NSS code NotFound gives no ranges */
+#[macro_export]
+macro_rules! subid_hooks {
+    ($hooks_ident: ident) => {
+        mod subid_hooks_impl {
+            use libnss::interop::Response;
+
+            use $crate::subid::*;
+
+            /** Find the ranges of an owner, converting errors to enum subid_status */
+            fn owner_ranges(owner: String, kind: SubidKind) -> Result<Vec<SubidRange>, libc::c_int> {
+                match <super::$hooks_ident as SubidHooks>::get_ranges_by_owner(owner, kind) {
+                    Response::Success(ranges) => Ok(ranges),
+                    Response::NotFound => Ok(Vec::new()),
+                    response => Err(failure_status(response)),
+                }
+            }
+
+            #[no_mangle]
+            unsafe extern "C" fn shadow_subid_has_range(
+                owner: *const libc::c_char,
+                start: libc::c_ulong,
+                count: libc::c_ulong,
+                idtype: libc::c_int,
+                result: *mut bool,
+            ) -> libc::c_int {
+                let (owner, kind) = match (owner_str(owner), SubidKind::from_c(idtype)) {
+                    (Some(owner), Some(kind)) => (owner, kind),
+                    _ => return SUBID_STATUS_ERROR,
+                };
+                match owner_ranges(owner, kind) {
+                    Ok(ranges) => {
+                        *result = covers(&ranges, start as u64, count as u64);
+                        SUBID_STATUS_SUCCESS
+                    }
+                    Err(status) => status,
+                }
+            }
+
+            #[no_mangle]
+            unsafe extern "C" fn shadow_subid_has_any_range(
+                owner: *const libc::c_char,
+                idtype: libc::c_int,
+                result: *mut bool,
+            ) -> libc::c_int {
+                let kind = match SubidKind::from_c(idtype) {
+                    Some(kind) => kind,
+                    None => return SUBID_STATUS_ERROR,
+                };
+                // Without an owner, check if anyone has a range
+                let ranges = match owner_str(owner) {
+                    Some(owner) => owner_ranges(owner, kind),
+                    None => match <super::$hooks_ident as SubidHooks>::get_all_entries(kind) {
+                        Response::Success(ranges) => Ok(ranges),
+                        Response::NotFound => Ok(Vec::new()),
+                        response => Err(failure_status(response)),
+                    },
+                };
+                match ranges {
+                    Ok(ranges) => {
+                        *result = !ranges.is_empty();
+                        SUBID_STATUS_SUCCESS
+                    }
+                    Err(status) => status,
+                }
+            }
+
+            #[no_mangle]
+            unsafe extern "C" fn shadow_subid_list_owner_ranges(
+                owner: *const libc::c_char,
+                idtype: libc::c_int,
+                ranges: *mut *mut CSubidRange,
+                count: *mut libc::c_int,
+            ) -> libc::c_int {
+                let (owner, kind) = match (owner_str(owner), SubidKind::from_c(idtype)) {
+                    (Some(owner), Some(kind)) => (owner, kind),
+                    _ => return SUBID_STATUS_ERROR,
+                };
+                let found = match owner_ranges(owner, kind) {
+                    Ok(found) => found,
+                    Err(status) => return status,
+                };
+                let found_count = found.len() as libc::c_int;
+                let c_ranges = found
+                    .into_iter()
+                    .map(|x| CSubidRange {start: x.start as libc::c_ulong, count: x.count as libc::c_ulong})
+                    .collect();
+                match malloc_array(c_ranges) {
+                    Some(array) => {
+                        *ranges = array;
+                        *count = found_count;
+                        SUBID_STATUS_SUCCESS
+                    }
+                    None => SUBID_STATUS_ERROR,
+                }
+            }
+
+            #[no_mangle]
+            unsafe extern "C" fn shadow_subid_find_subid_owners(
+                id: libc::c_ulong,
+                idtype: libc::c_int,
+                uids: *mut *mut libc::uid_t,
+                count: *mut libc::c_int,
+            ) -> libc::c_int {
+                let kind = match SubidKind::from_c(idtype) {
+                    Some(kind) => kind,
+                    None => return SUBID_STATUS_ERROR,
+                };
+                let found = match <super::$hooks_ident as SubidHooks>::get_owners_by_id(id as u64, kind) {
+                    Response::Success(found) => found,
+                    Response::NotFound => Vec::new(),
+                    response => return failure_status(response),
+                };
+                let mut owner_uids: Vec<libc::uid_t> = Vec::new();
+                for range in found {
+                    match owner_uid(&range.owner) {
+                        Some(uid) if !owner_uids.contains(&uid) => owner_uids.push(uid),
+                        Some(_) => {}
+                        None => return SUBID_STATUS_UNKNOWN_USER,
+                    }
+                }
+                let found_count = owner_uids.len() as libc::c_int;
+                match malloc_array(owner_uids) {
+                    Some(array) => {
+                        *uids = array;
+                        *count = found_count;
+                        SUBID_STATUS_SUCCESS
+                    }
+                    None => SUBID_STATUS_ERROR,
+                }
+            }
+
+            #[no_mangle]
+            unsafe extern "C" fn shadow_subid_free(ptr: *mut libc::c_void) {
+                libc::free(ptr);
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(owner: &str, start: u64, count: u64) -> SubidRange {
+        SubidRange {
+            owner: owner.to_string(),
+            start,
+            count,
+        }
+    }
+
+    #[test]
+    fn accepts_ranges_which_do_not_overlap() {
+        assert_eq!(validate_ranges(&[]), Ok(()));
+        let ranges = [range("bob", 165536, 65536), range("alice", 100000, 65536), range("alice", 300000, 1)];
+        assert_eq!(validate_ranges(&ranges), Ok(()));
+        // The last range can end at the largest possible id
+        assert_eq!(validate_ranges(&[range("alice", u32::MAX as u64, 1)]), Ok(()));
+    }
+
+    #[test]
+    fn rejects_overlapping_ranges() {
+        let ranges = [range("alice", 100000, 65536), range("bob", 165535, 10)];
+        assert_eq!(
+            validate_ranges(&ranges),
+            Err("Range bob:165535:10 overlaps range alice:100000:65536".to_string())
+        );
+        // Ranges of the same owner cannot overlap either, even when given out of order
+        let ranges = [range("alice", 200000, 10), range("alice", 100000, 200000)];
+        assert!(validate_ranges(&ranges).is_err());
+        let ranges = [range("alice", 100000, 10), range("bob", 100000, 10)];
+        assert!(validate_ranges(&ranges).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_and_out_of_bounds_ranges() {
+        assert!(validate_ranges(&[range("alice", 100000, 0)]).is_err());
+        assert!(validate_ranges(&[range("alice", u32::MAX as u64, 2)]).is_err());
+        assert!(validate_ranges(&[range("alice", u64::MAX, 1)]).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+//! libnss_shim-ctl: control the caches of libnss_shim
+//!
+//! `libnss_shim-ctl flush [database] [key]` invalidates cached results in every process, either for all databases, a
+//! single database, or a single name/id within a database.
+
+use std::env;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: libnss_shim-ctl flush [database] [key]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|x| x.as_str()) {
+        Some("flush") if args.len() <= 3 => {
+            let database = args.get(1).map(|x| x.as_str());
+            let key = args.get(2).map(|x| x.as_str());
+            match nss_shim::flush(database, key) {
+                Ok(()) => {
+                    match (database, key) {
+                        (None, _) => println!("Flushed all databases"),
+                        (Some(database), None) => println!("Flushed {}", database),
+                        (Some(database), Some(key)) => println!("Flushed {} in {}", key, database),
+                    }
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Unable to flush caches: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! libnss_shim-refresh: build the index files used by the `index` backend
+//!
+//! Runs `get_all_entries` for each database given as an argument (or for group, passwd, and shadow by default) and
+//! writes the validated entries to the database's index file.
+
+use std::env;
+use std::process::ExitCode;
+
+use libnss::interop::Response;
+
+fn main() -> ExitCode {
+    let mut databases: Vec<String> = env::args().skip(1).collect();
+    if databases.iter().any(|x| x == "-h" || x == "--help") {
+        println!("Usage: libnss_shim-refresh [database...]");
+        println!("Build index files for the given databases (default: group passwd shadow)");
+        return ExitCode::SUCCESS;
+    }
+    if databases.is_empty() {
+        databases = vec!["group".to_string(), "passwd".to_string(), "shadow".to_string()];
+    }
+    let mut code = ExitCode::SUCCESS;
+    for database in databases {
+        match nss_shim::refresh_index(&database) {
+            Response::Success(count) => println!("{}: wrote {} entries", database, count),
+            response => {
+                eprintln!(
+                    "{}: unable to refresh index ({:?}), set \"debug\": true in config.json for details",
+                    database,
+                    response.to_status()
+                );
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+    code
+}
@@ -0,0 +1,750 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::DirBuilder;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use libnss::interop::Response;
+use nss_shim_ffi::lock::lock_exclusive;
+
+use crate::failure;
+use crate::find_setting;
+
+/// Default number of seconds to keep a negative result
+const DEFAULT_NEGATIVE_TTL: u64 = 10;
+/// Default maximum number of negative results to keep per process
+const DEFAULT_NEGATIVE_MAX_ENTRIES: usize = 1024;
+/// Default number of seconds to use a result from the on-disk cache
+const DEFAULT_DISK_TTL: u64 = 60;
+/// Directory containing the on-disk cache shared across processes, with one file per database
+const DISK_CACHE_DIR: &str = "/var/cache/libnss_shim";
+/// Databases with sensitive entries, which must only be cached in files readable by root
+pub(crate) const PRIVATE_DATABASES: [&str; 2] = ["shadow", "gshadow"];
+
+/// File recording when caches were last flushed by libnss_shim-ctl, checked by every process before using a cache
+const GENERATION_PATH: &str = "/run/libnss_shim/generation";
+/// Lock file held by libnss_shim-ctl while it updates the generation file, which is replaced rather than modified
+const GENERATION_LOCK_PATH: &str = "/run/libnss_shim/generation.lock";
+/// Path of config.json, read by libnss_shim-ctl to find out how long flushes of single keys need to be kept
+const CONFIG_PATH: &str = "/etc/libnss_shim/config.json";
+
+/// Socket of the system logger, used for warnings which are logged even without `debug`
+const SYSLOG_PATH: &str = "/dev/log";
+
+/// Counter to keep temporary file names unique within a process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/** Negative results which can be stored in the negative cache */
+#[derive(Clone, Copy, Debug)]
+enum Negative {
+    NotFound,
+    TryAgain,
+    Unavail,
+}
+
+impl Negative {
+    fn to_response<T>(self) -> Response<T> {
+        match self {
+            Negative::NotFound => Response::NotFound,
+            Negative::TryAgain => Response::TryAgain,
+            Negative::Unavail => Response::Unavail,
+        }
+    }
+}
+
+/** Negative cache settings as defined by the `negative_cache` object in config.json */
+struct NegativeSettings {
+    ttl: Duration,
+    max_entries: usize,
+    errors: bool,
+}
+
+/// Cached negative results with their expiry and creation time, keyed by database, function, and query
+type NegativeEntries = HashMap<(String, String, String), (Negative, Instant, u64)>;
+
+static NEGATIVE_CACHE: OnceLock<Mutex<NegativeEntries>> = OnceLock::new();
+
+/// Identifies a version of the generation file: device, inode, modification time, and size
+type GenerationId = (u64, u64, i64, i64, u64);
+
+/// Last version of the generation file read by this process, so that it is only parsed again when it changes
+static GENERATION: OnceLock<Mutex<Option<(GenerationId, serde_json::Value)>>> = OnceLock::new();
+
+/** Read the `negative_cache` settings for a database function
+- If negative caching is not configured or is disabled with a ttl of 0, return Ok(None)
+- If the settings are not formatted as expected, return Err */
+fn negative_settings(
+    config: &serde_json::Value,
+    database: &str,
+    function: &str,
+    debug: bool,
+) -> Result<Option<NegativeSettings>, ()> {
+    let settings = match find_setting(config, database, function, "negative_cache") {
+        Some(settings) => settings,
+        None => return Ok(None),
+    };
+    let settings = match settings.as_object() {
+        Some(settings) => settings,
+        None => {
+            debug_print!("negative_cache in config.json must be an object/map", debug);
+            return Err(());
+        }
+    };
+    let ttl = match settings.get("ttl") {
+        Some(ttl) => match ttl.as_u64() {
+            Some(ttl) => ttl,
+            None => {
+                debug_print!("negative_cache ttl in config.json must be a positive integer", debug);
+                return Err(());
+            }
+        },
+        None => DEFAULT_NEGATIVE_TTL,
+    };
+    let max_entries = match settings.get("max_entries") {
+        Some(max_entries) => match max_entries.as_u64().and_then(|x| usize::try_from(x).ok()) {
+            Some(max_entries) => max_entries,
+            None => {
+                debug_print!(
+                    "negative_cache max_entries in config.json must be a positive integer",
+                    debug
+                );
+                return Err(());
+            }
+        },
+        None => DEFAULT_NEGATIVE_MAX_ENTRIES,
+    };
+    let errors = match settings.get("errors") {
+        Some(errors) => match errors.as_bool() {
+            Some(errors) => errors,
+            None => {
+                debug_print!("negative_cache errors in config.json must be a boolean", debug);
+                return Err(());
+            }
+        },
+        None => false,
+    };
+    if ttl == 0 || max_entries == 0 {
+        return Ok(None);
+    }
+    Ok(Some(NegativeSettings {
+        ttl: Duration::from_secs(ttl),
+        max_entries,
+        errors,
+    }))
+}
+
+/** Determine if a file shared between processes can be trusted from its owner and mode
+- It must be owned by root and not writable by other users, who could otherwise change what every process is given */
+pub(crate) fn trusted(uid: u32, mode: u32) -> bool {
+    uid == 0 && mode & 0o022 == 0
+}
+
+/** Run a lookup through the negative cache
+- If a matching negative result has not expired yet, return it without running the lookup
+- NotFound results of the lookup are cached; TryAgain/Unavail are only cached if `errors` is enabled
+- If negative caching is not configured, the lookup is run directly */
+pub fn with_negative_cache<T>(
+    config: &serde_json::Value,
+    database: &str,
+    function: &str,
+    query: &str,
+    debug: bool,
+    lookup: impl FnOnce() -> Response<T>,
+) -> Response<T> {
+    let settings = match negative_settings(config, database, function, debug) {
+        Ok(Some(settings)) => settings,
+        Ok(None) => return lookup(),
+        Err(()) => return Response::Unavail,
+    };
+    let cache = NEGATIVE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    let key = (database.to_string(), function.to_string(), query.to_string());
+    // Check for a cached result, making sure not to hold the lock while the lookup runs
+    {
+        let mut entries = cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((negative, expiry, created)) = entries.get(&key) {
+            if *expiry > Instant::now() && *created > flushed_at(database, function, query, debug) {
+                debug_print!(
+                    format!("Using cached {:?} for {} {} {}", negative, database, function, query),
+                    debug
+                );
+                return negative.to_response();
+            }
+            entries.remove(&key);
+        }
+    }
+    let response = lookup();
+    let negative = match response {
+        Response::NotFound => Negative::NotFound,
+        Response::TryAgain if settings.errors => Negative::TryAgain,
+        Response::Unavail if settings.errors => Negative::Unavail,
+        _ => return response,
+    };
+    let mut entries = cache.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    if entries.len() >= settings.max_entries {
+        // Make room by dropping expired results first, then the results closest to expiring
+        entries.retain(|_, (_, expiry, _)| *expiry > now);
+        while entries.len() >= settings.max_entries {
+            let oldest = match entries.iter().min_by_key(|(_, (_, expiry, _))| *expiry) {
+                Some((oldest, _)) => oldest.clone(),
+                None => break,
+            };
+            entries.remove(&oldest);
+        }
+    }
+    entries.insert(key, (negative, now + settings.ttl, now_secs()));
+    response
+}
+
+/** Get the time in seconds since the epoch at which cached results for a query were last flushed
+- Flushes of all databases, of the whole database, and of the specific query all apply
+- Results of get_all_entries (including each page, which is queried by its offset and cursor) and other enumerations with
+  a blank query are also flushed when any query in the database is flushed
+- The generation file is only trusted if it is owned by root and not writable by other users
+- If nothing has been flushed, return 0 */
+fn flushed_at(database: &str, function: &str, query: &str, debug: bool) -> u64 {
+    let metadata = match fs::metadata(GENERATION_PATH) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !trusted(metadata.uid(), metadata.mode()) {
+        debug_print!(format!("Ignoring untrusted generation file: {}", GENERATION_PATH), debug);
+        return 0;
+    }
+    let id = (
+        metadata.dev(),
+        metadata.ino(),
+        metadata.mtime(),
+        metadata.mtime_nsec(),
+        metadata.size(),
+    );
+    let generation = GENERATION.get_or_init(|| Mutex::new(None));
+    let mut generation = generation.lock().unwrap_or_else(|e| e.into_inner());
+    let flushes = match &*generation {
+        Some((cached_id, flushes)) if *cached_id == id => flushes,
+        _ => {
+            let flushes = read_generation();
+            &generation.insert((id, flushes)).1
+        }
+    };
+    let database_flushes = &flushes["databases"][database];
+    [
+        flushes["all"].as_u64(),
+        database_flushes["all"].as_u64(),
+        key_flushed_at(database_flushes, function, query),
+    ]
+    .into_iter()
+    .flatten()
+    .max()
+    .unwrap_or(0)
+}
+
+/** Get the time at which the key of a query was last flushed, from the flushes of its database in the generation file
+- Enumerations are flushed by the flush of any key, since they include every entry
+- If the key has not been flushed, return None */
+fn key_flushed_at(database_flushes: &serde_json::Value, function: &str, query: &str) -> Option<u64> {
+    if query.is_empty() || function == "get_all_entries" {
+        return database_flushes["keys"]
+            .as_object()
+            .and_then(|keys| keys.values().filter_map(|x| x.as_u64()).max());
+    }
+    // Queries which also depend on something else (e.g. the address family in `name/ipv4`) are flushed by their name
+    let key = query.split('/').next().unwrap_or(query);
+    [database_flushes["keys"][query].as_u64(), database_flushes["keys"][key].as_u64()]
+        .into_iter()
+        .flatten()
+        .max()
+}
+
+/** Read the generation file, returning an empty object if it is missing or invalid */
+fn read_generation() -> serde_json::Value {
+    let mut content = String::new();
+    match File::open(GENERATION_PATH).and_then(|mut file| file.read_to_string(&mut content)) {
+        Ok(_) => match serde_json::from_str::<serde_json::Value>(&content) {
+            Ok(flushes) if flushes.is_object() => flushes,
+            _ => serde_json::json!({}),
+        },
+        Err(_) => serde_json::json!({}),
+    }
+}
+
+/** Flush cached results in every process by recording the current time in the generation file
+- Without a database, results for all databases are flushed
+- With a key (a name or id), only results of lookups for that key and of get_all_entries are flushed
+- Flushes of keys are removed once they are older than every cache ttl, since no result cached before them is still used
+- The generation file is replaced atomically, while holding a lock so that concurrent flushes are not lost */
+pub fn flush(database: Option<&str>, key: Option<&str>) -> io::Result<()> {
+    let directory = Path::new(GENERATION_PATH).parent().unwrap_or(Path::new("/"));
+    DirBuilder::new().recursive(true).mode(0o755).create(directory)?;
+    // The lock is released when the file is closed at the end of this function
+    let lock = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(GENERATION_LOCK_PATH)?;
+    lock_exclusive(&lock)?;
+    let mut flushes = read_generation();
+    let now = now_secs();
+    if let Some(max_ttl) = max_cache_ttl() {
+        prune_key_flushes(&mut flushes, now.saturating_sub(max_ttl));
+    }
+    let now = serde_json::json!(now);
+    match (database, key) {
+        (None, _) => flushes["all"] = now,
+        (Some(database), None) => flushes["databases"][database]["all"] = now,
+        (Some(database), Some(key)) => flushes["databases"][database]["keys"][key] = now,
+    }
+    let temp_path = format!("{}.{}", GENERATION_PATH, process::id());
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o644)
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(flushes.to_string().as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, GENERATION_PATH));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/** Remove flushes of keys from before a time, along with databases which are left without any flushes */
+fn prune_key_flushes(flushes: &mut serde_json::Value, before: u64) {
+    let databases = match flushes["databases"].as_object_mut() {
+        Some(databases) => databases,
+        None => return,
+    };
+    for database_flushes in databases.values_mut() {
+        if let Some(keys) = database_flushes["keys"].as_object_mut() {
+            keys.retain(|_, time| time.as_u64().is_some_and(|x| x >= before));
+        }
+        if let Some(database_flushes) = database_flushes.as_object_mut() {
+            database_flushes.retain(|name, value| name != "keys" || value.as_object().is_some_and(|x| !x.is_empty()));
+        }
+    }
+    databases.retain(|_, database_flushes| database_flushes.as_object().is_some_and(|x| !x.is_empty()));
+}
+
+/** Get the largest number of seconds for which any cache in config.json can use a result
+- The on-disk cache can use results until its ttl and stale_if_error have both passed
+- If config.json cannot be read, return None, since it is unknown how long results are kept */
+fn max_cache_ttl() -> Option<u64> {
+    let content = fs::read_to_string(CONFIG_PATH).ok()?;
+    let config = serde_json::from_str::<serde_json::Value>(&content).ok()?;
+    // Settings can be given globally, per database, or per function
+    let mut levels = vec![&config];
+    if let Some(databases) = config["databases"].as_object() {
+        for database in databases.values() {
+            levels.push(database);
+            if let Some(functions) = database["functions"].as_object() {
+                levels.extend(functions.values());
+            }
+        }
+    }
+    let mut max_ttl = 0;
+    for level in levels {
+        if let Some(settings) = level.get("negative_cache") {
+            max_ttl = max_ttl.max(settings["ttl"].as_u64().unwrap_or(DEFAULT_NEGATIVE_TTL));
+        }
+        if let Some(settings) = level.get("disk_cache") {
+            let ttl = settings["ttl"].as_u64().unwrap_or(DEFAULT_DISK_TTL);
+            max_ttl = max_ttl.max(ttl.saturating_add(settings["stale_if_error"].as_u64().unwrap_or(0)));
+        }
+    }
+    Some(max_ttl)
+}
+
+/** Get the current time in seconds since the epoch */
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+/** On-disk cache settings as defined by the `disk_cache` object in config.json */
+struct DiskSettings {
+    ttl: u64,
+    stale_if_error: u64,
+}
+
+/** Read the `disk_cache` settings for a database function
+- If the on-disk cache is not configured or is disabled with a ttl of 0, return Ok(None)
+- If the settings are not formatted as expected, return Err */
+fn disk_settings(
+    config: &serde_json::Value,
+    database: &str,
+    function: &str,
+    debug: bool,
+) -> Result<Option<DiskSettings>, ()> {
+    let settings = match find_setting(config, database, function, "disk_cache") {
+        Some(settings) => settings,
+        None => return Ok(None),
+    };
+    let settings = match settings.as_object() {
+        Some(settings) => settings,
+        None => {
+            debug_print!("disk_cache in config.json must be an object/map", debug);
+            return Err(());
+        }
+    };
+    let ttl = match settings.get("ttl") {
+        Some(ttl) => match ttl.as_u64() {
+            Some(ttl) => ttl,
+            None => {
+                debug_print!("disk_cache ttl in config.json must be a positive integer", debug);
+                return Err(());
+            }
+        },
+        None => DEFAULT_DISK_TTL,
+    };
+    let stale_if_error = match settings.get("stale_if_error") {
+        Some(stale_if_error) => match stale_if_error.as_u64() {
+            Some(stale_if_error) => stale_if_error,
+            None => {
+                debug_print!(
+                    "disk_cache stale_if_error in config.json must be a positive integer",
+                    debug
+                );
+                return Err(());
+            }
+        },
+        None => 0,
+    };
+    if ttl == 0 {
+        // Stale results can only be returned from the on-disk cache, so they need it to be enabled
+        if stale_if_error > 0 {
+            debug_print!("disk_cache stale_if_error in config.json requires a ttl greater than 0", debug);
+            return Err(());
+        }
+        return Ok(None);
+    }
+    Ok(Some(DiskSettings {
+        ttl,
+        stale_if_error,
+    }))
+}
+
+/** Determine if the on-disk cache is enabled for a database function, in which case the whole command output is cached
+- If the settings are invalid, return true, so that the error is reported by with_disk_cache */
+pub fn uses_disk_cache(config: &serde_json::Value, database: &str, function: &str, debug: bool) -> bool {
+    !matches!(disk_settings(config, database, function, debug), Ok(None))
+}
+
+/** Get the path of the on-disk cache file of a database, which holds the results of all of its functions and queries */
+fn disk_cache_path(database: &str) -> String {
+    format!("{}/{}.json", DISK_CACHE_DIR, database)
+}
+
+/** Read the on-disk cache file of a database
+- The file is a JSON object mapping each function to an object of its queries, each with the time and output cached
+- The file is only trusted if it is owned by root and not writable by other users
+- If the file is missing, untrusted, or invalid, return None */
+fn read_disk_cache_file(database: &str, debug: bool) -> Option<serde_json::Value> {
+    let path = disk_cache_path(database);
+    let mut file = File::open(&path).ok()?;
+    match file.metadata() {
+        Ok(metadata) if trusted(metadata.uid(), metadata.mode()) => {}
+        _ => {
+            debug_print!(format!("Ignoring untrusted cache file: {}", path), debug);
+            return None;
+        }
+    }
+    let mut content = String::new();
+    if file.read_to_string(&mut content).is_err() {
+        debug_print!(format!("Unable to read cache file: {}", path), debug);
+        return None;
+    }
+    match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(cache) if cache.is_object() => Some(cache),
+        _ => {
+            debug_print!(format!("Ignoring invalid cache file: {}", path), debug);
+            None
+        }
+    }
+}
+
+/** Read the cached output for a query of a database function, with the time it was cached
+- If the cache file is missing, untrusted, or invalid, or has no result for the query, return None */
+fn read_disk_cache(database: &str, function: &str, query: &str, debug: bool) -> Option<(u64, String)> {
+    cached_output(read_disk_cache_file(database, debug)?, function, query)
+}
+
+/** Take the output for a query of a database function out of the contents of a cache file, with the time it was cached
+- If there is no valid result for the query, return None */
+fn cached_output(mut cache: serde_json::Value, function: &str, query: &str) -> Option<(u64, String)> {
+    let cached = cache[function][query].take();
+    match (cached["time"].as_u64(), cached["output"].as_str()) {
+        (Some(time), Some(output)) => Some((time, output.to_string())),
+        _ => None,
+    }
+}
+
+/** Store the output for a query of a database function in the contents of a cache file
+- Results of the function which are older than `max_age` at the time of the new result are removed */
+fn store_output(cache: &mut serde_json::Value, function: &str, query: &str, time: u64, output: &str, max_age: u64) {
+    if let Some(queries) = cache[function].as_object_mut() {
+        queries.retain(|_, cached| cached["time"].as_u64().is_some_and(|x| time.saturating_sub(x) < max_age));
+    }
+    cache[function][query] = serde_json::json!({"time": time, "output": output});
+}
+
+/** Store the output for a query of a database function in the database's on-disk cache file
+- Results of the function which are too old to be used (older than `max_age`) are removed, so the file does not grow
+  forever
+- The file is updated while holding a lock, so that results written by other processes at the same time are not lost
+- The new contents are written to a temporary file in the same directory, which is then renamed
+- Files for private databases like shadow are only readable by root
+- Failures are not fatal (e.g. processes not running as root cannot write to the cache) */
+fn write_disk_cache(database: &str, function: &str, query: &str, time: u64, output: &str, max_age: u64, debug: bool) {
+    let private = PRIVATE_DATABASES.contains(&database);
+    if let Err(e) = DirBuilder::new().recursive(true).mode(0o755).create(DISK_CACHE_DIR) {
+        debug_print!(format!("Unable to create cache directory: {}", e), debug);
+        return;
+    }
+    let path = disk_cache_path(database);
+    // The lock is released when the file is closed at the end of this function
+    let lock = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(format!("{}/.{}.lock", DISK_CACHE_DIR, database))
+        .and_then(|lock| lock_exclusive(&lock).map(|_| lock));
+    if let Err(e) = lock {
+        debug_print!(format!("Unable to lock cache file: {}: {}", path, e), debug);
+        return;
+    }
+    let mut cache = read_disk_cache_file(database, debug).unwrap_or_else(|| serde_json::json!({}));
+    store_output(&mut cache, function, query, time, output, max_age);
+    let temp_path = format!(
+        "{}/.{}.{}.{}",
+        DISK_CACHE_DIR,
+        database,
+        process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(if private { 0o600 } else { 0o644 })
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(cache.to_string().as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, &path));
+    if let Err(e) = result {
+        debug_print!(format!("Unable to write cache file: {}: {}", path, e), debug);
+        let _ = fs::remove_file(&temp_path);
+    }
+}
+
+/** Run a command and parse its output through the on-disk cache
+- If a fresh copy of the output for this query is cached on disk, parse it instead of running the command
+- If the command output is parsed successfully, store it in the cache for other processes
+- If the on-disk cache is not configured, the command is run directly */
+pub fn with_disk_cache<T>(
+    config: &serde_json::Value,
+    database: &str,
+    function: &str,
+    query: &str,
+    debug: bool,
+    run: impl FnOnce() -> Response<String>,
+    parse: impl Fn(&str) -> Response<T>,
+) -> Response<T> {
+    let settings = match disk_settings(config, database, function, debug) {
+        Ok(Some(settings)) => settings,
+        Ok(None) => {
+            return match run() {
+                Response::Success(output) => parse(&output),
+                response => failure(response),
+            }
+        }
+        Err(()) => return Response::Unavail,
+    };
+    // Results older than the ttl are kept until the stale_if_error window has passed too
+    let max_age = settings.ttl.saturating_add(settings.stale_if_error);
+    let now = now_secs();
+    // Results cached before the last flush by libnss_shim-ctl are never used, not even when stale
+    let flushed = flushed_at(database, function, query, debug);
+    let cached = read_disk_cache(database, function, query, debug)
+        .filter(|(time, _)| *time > flushed)
+        .map(|(time, output)| (now.saturating_sub(time), output));
+    if let Some((age, output)) = &cached {
+        if *age < settings.ttl {
+            debug_print!(format!("Using cached output for {} {} {}", database, function, query), debug);
+            match parse(output) {
+                Response::Success(result) => return Response::Success(result),
+                _ => debug_print!("Cached output is invalid, running command", debug),
+            }
+        }
+    }
+    let response = match run() {
+        Response::Success(output) => {
+            let response = parse(&output);
+            if let Response::Success(_) = response {
+                write_disk_cache(database, function, query, now, &output, max_age, debug);
+            }
+            response
+        }
+        response => failure(response),
+    };
+    // If the command failed, fall back to the last known good result within the stale_if_error window
+    if let Response::TryAgain | Response::Unavail = response {
+        if let Some((age, output)) = cached {
+            if age < max_age {
+                // Stale results are always logged, so that they are noticed even without debug
+                log_warning(&format!(
+                    "Using stale cached output ({} seconds old) for {} {} {} after error",
+                    age, database, function, query
+                ));
+                if let Response::Success(result) = parse(&output) {
+                    return Response::Success(result);
+                }
+            }
+        }
+    }
+    response
+}
+
+/** Log a warning to the system logger (with the `user` facility), whether or not `debug` is enabled
+- If the system logger is not available, print the warning to stderr instead */
+fn log_warning(message: &str) {
+    // Priority 12 is the `warning` level of the `user` facility
+    let line = format!("<12>libnss_shim[{}]: {}", process::id(), message);
+    let sent = UnixDatagram::unbound().and_then(|socket| socket.send_to(line.as_bytes(), SYSLOG_PATH));
+    if sent.is_err() {
+        eprintln!("libnss_shim: warning: {}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn trusts_only_root_owned_files_not_writable_by_others() {
+        assert!(trusted(0, 0o100644));
+        assert!(trusted(0, 0o100600));
+        assert!(!trusted(0, 0o100664));
+        assert!(!trusted(0, 0o100646));
+        assert!(!trusted(1000, 0o100644));
+    }
+
+    #[test]
+    fn matches_key_flushes_to_queries() {
+        let flushes = serde_json::json!({"keys": {"alice": 10, "alice/ipv6": 20, "1000": 30}});
+        assert_eq!(key_flushed_at(&flushes, "get_entry_by_name", "alice"), Some(10));
+        assert_eq!(key_flushed_at(&flushes, "get_entry_by_uid", "1000"), Some(30));
+        assert_eq!(key_flushed_at(&flushes, "get_entry_by_name", "bob"), None);
+        // Queries which also depend on something else are flushed by their name too
+        assert_eq!(key_flushed_at(&flushes, "get_entry_by_name", "alice/ipv4"), Some(10));
+        assert_eq!(key_flushed_at(&flushes, "get_entry_by_name", "alice/ipv6"), Some(20));
+        // Enumerations, including each page of get_all_entries, are flushed by any key
+        assert_eq!(key_flushed_at(&flushes, "get_all_entries", ""), Some(30));
+        assert_eq!(key_flushed_at(&flushes, "get_all_names", ""), Some(30));
+        assert_eq!(key_flushed_at(&flushes, "get_all_entries", "2/alice"), Some(30));
+        assert_eq!(key_flushed_at(&serde_json::json!({}), "get_all_entries", ""), None);
+    }
+
+    #[test]
+    fn prunes_old_key_flushes() {
+        let mut flushes = serde_json::json!({
+            "all": 5,
+            "databases": {
+                "passwd": {"all": 5, "keys": {"alice": 10, "bob": 20}},
+                "group": {"keys": {"users": 10}},
+            },
+        });
+        prune_key_flushes(&mut flushes, 15);
+        assert_eq!(
+            flushes,
+            serde_json::json!({"all": 5, "databases": {"passwd": {"all": 5, "keys": {"bob": 20}}}})
+        );
+    }
+
+    #[test]
+    fn stores_and_reads_cached_output() {
+        let mut cache = serde_json::json!({});
+        store_output(&mut cache, "get_entry_by_name", "alice", 100, "alice:x:1000", 60);
+        store_output(&mut cache, "get_entry_by_uid", "1000", 100, "alice:x:1000", 60);
+        assert_eq!(
+            cached_output(cache.clone(), "get_entry_by_name", "alice"),
+            Some((100, "alice:x:1000".to_string()))
+        );
+        assert_eq!(cached_output(cache.clone(), "get_entry_by_name", "bob"), None);
+        // Results of the same function which are too old to be used are removed when a result is stored
+        store_output(&mut cache, "get_entry_by_name", "bob", 200, "bob:x:1001", 60);
+        assert_eq!(cached_output(cache.clone(), "get_entry_by_name", "alice"), None);
+        assert_eq!(cached_output(cache.clone(), "get_entry_by_name", "bob"), Some((200, "bob:x:1001".to_string())));
+        assert_eq!(cached_output(cache.clone(), "get_entry_by_uid", "1000"), Some((100, "alice:x:1000".to_string())));
+        // Invalid results are ignored
+        let cache = serde_json::json!({"get_entry_by_name": {"alice": {"time": "100", "output": "alice:x:1000"}}});
+        assert_eq!(cached_output(cache, "get_entry_by_name", "alice"), None);
+    }
+
+    #[test]
+    fn caches_negative_results() {
+        // The negative cache is shared by the whole process, so it is only used by this test
+        let config = serde_json::json!({
+            "databases": {
+                "test": {"negative_cache": {"ttl": 60, "max_entries": 2}},
+                "test_errors": {"negative_cache": {"ttl": 60, "errors": true}},
+            },
+        });
+        let runs = Cell::new(0);
+        let lookup = |query: &str, response: Response<()>| {
+            with_negative_cache(&config, "test", "get_entry_by_name", query, false, || {
+                runs.set(runs.get() + 1);
+                response
+            })
+        };
+        assert_eq!(lookup("a", Response::NotFound), Response::NotFound);
+        assert_eq!(lookup("a", Response::Success(())), Response::NotFound);
+        assert_eq!(runs.get(), 1);
+        // Successful results are not cached
+        assert_eq!(lookup("b", Response::Success(())), Response::Success(()));
+        assert_eq!(lookup("b", Response::NotFound), Response::NotFound);
+        assert_eq!(runs.get(), 3);
+        // Errors are only cached if enabled
+        assert_eq!(lookup("c", Response::TryAgain), Response::TryAgain);
+        assert_eq!(lookup("c", Response::Unavail), Response::Unavail);
+        assert_eq!(runs.get(), 5);
+        let errors = with_negative_cache(&config, "test_errors", "get_entry_by_name", "c", false, || Response::<()>::Unavail);
+        assert_eq!(errors, Response::Unavail);
+        let errors = with_negative_cache(&config, "test_errors", "get_entry_by_name", "c", false, || Response::Success(()));
+        assert_eq!(errors, Response::Unavail);
+        // max_entries counts the results of every database, so only those of the first database are kept
+        // Then, with max_entries reached, the result closest to expiring ("a") is evicted to make room
+        NEGATIVE_CACHE.get().unwrap().lock().unwrap().retain(|(database, _, _), _| database == "test");
+        assert_eq!(lookup("d", Response::NotFound), Response::NotFound);
+        assert_eq!(runs.get(), 6);
+        assert_eq!(lookup("b", Response::Success(())), Response::NotFound);
+        assert_eq!(lookup("d", Response::Success(())), Response::NotFound);
+        assert_eq!(runs.get(), 6);
+        assert_eq!(lookup("a", Response::Success(())), Response::Success(()));
+        assert_eq!(runs.get(), 7);
+    }
+}
@@ -0,0 +1,615 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::DirBuilder;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::process;
+
+use libnss::interop::Response;
+
+use crate::cache::trusted;
+use crate::cache::PRIVATE_DATABASES;
+use crate::find_setting;
+
+/// Directory containing the index files built by libnss_shim-refresh, with one file per database
+pub const INDEX_DIR: &str = "/var/lib/libnss_shim";
+
+/// Identifies a libnss_shim index file
+const MAGIC: &[u8; 8] = b"NSSSHIDX";
+/// Version of the index file layout, which must be increased whenever the layout changes
+const VERSION: u32 = 2;
+/// Size of the header at the start of an index file
+const HEADER_LEN: u64 = 40;
+/// Size of each entry in the id table
+const ID_ENTRY_LEN: u64 = 16;
+/// Flag set on records which are hidden from enumeration
+const HIDDEN: u32 = 1;
+
+/*
+Index file layout (all integers are little-endian):
+
+Header:
+  magic (8 bytes) | version (u32) | name count (u32) | id count (u32) | reserved (u32) |
+  name table offset (u64) | id table offset (u64)
+Name table, sorted by name:
+  record offset (u64) for each record
+Id table, sorted by id:
+  id (u32) | reserved (u32) | record offset (u64) for each record with an id
+Records:
+  field count (u32) | flags (u32), then for each field: length (u32) | UTF-8 bytes
+  The only flag is 1, set if the record is hidden from enumeration
+  The first field of each record is its name, and fields are in the same order as the colon-separated file format
+*/
+
+/** A single entry to be written to an index file */
+pub struct Record {
+    pub name: String,
+    pub id: Option<u32>,
+    pub hidden: bool,
+    pub fields: Vec<String>,
+}
+
+/** Changes to a database since a token, as returned by get_changes_since
+- Hidden has the positions of the upserts which are hidden from enumeration */
+pub struct Changes<T> {
+    pub token: String,
+    pub upserts: Vec<T>,
+    pub hidden: HashSet<usize>,
+    pub deletions: Vec<String>,
+    pub resync: bool,
+}
+
+/** Key used to look up a record in an index file */
+pub enum Key<'a> {
+    Name(&'a str),
+    Id(u32),
+}
+
+/** Get the path of the index file for a database */
+pub fn index_path(database: &str) -> String {
+    format!("{}/{}.idx", INDEX_DIR, database)
+}
+
+/** Get the path of the file containing the get_changes_since token for a database's index file */
+fn token_path(database: &str) -> String {
+    format!("{}/{}.token", INDEX_DIR, database)
+}
+
+/** Get the position of the id among the fields of a database's records, if it has one */
+fn id_field(database: &str) -> Option<usize> {
+    match database {
+        "group" | "passwd" => Some(2),
+        _ => None,
+    }
+}
+
+/** Determine if lookups for a database function should be answered from its index file
+- This is the case if `backend` is set to `index`; the default backend `command` runs commands
+- If `backend` is not one of those values, return Err */
+pub fn use_index(
+    config: &serde_json::Value,
+    database: &str,
+    function: &str,
+    debug: bool,
+) -> Result<bool, ()> {
+    match find_setting(config, database, function, "backend").map(|x| x.as_str()) {
+        None | Some(Some("command")) => Ok(false),
+        Some(Some("index")) => Ok(true),
+        _ => {
+            debug_print!("backend in config.json must be either \"command\" or \"index\"", debug);
+            Err(())
+        }
+    }
+}
+
+/** Write the records of a database to its index file
+- The file is written to a temporary path in the same directory, which is then renamed
+- Index files for private databases like shadow are only readable by root */
+pub fn write_index(database: &str, records: &[Record]) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o755).create(INDEX_DIR)?;
+    let mode = if PRIVATE_DATABASES.contains(&database) { 0o600 } else { 0o644 };
+    let path = index_path(database);
+    let temp_path = format!("{}/.{}.idx.{}", INDEX_DIR, database, process::id());
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(&temp_path)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write_records(&mut writer, records)?;
+            writer.into_inner()?.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/** Read the get_changes_since token saved by the last refresh of a database's index file
+- If there is none, or it is empty, return None */
+pub fn read_token(database: &str) -> Option<String> {
+    fs::read_to_string(token_path(database)).ok().filter(|x| !x.is_empty())
+}
+
+/** Save the get_changes_since token for the next refresh of a database's index file, or remove it if there is none
+- The file is written to a temporary path in the same directory, which is then renamed */
+pub fn write_token(database: &str, token: Option<&str>) -> io::Result<()> {
+    let path = token_path(database);
+    let token = match token {
+        Some(token) => token,
+        None => {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        }
+    };
+    let temp_path = format!("{}/.{}.token.{}", INDEX_DIR, database, process::id());
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(token.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/** Serialize records in the index file layout */
+fn write_records(writer: &mut impl Write, records: &[Record]) -> io::Result<()> {
+    let count = u32::try_from(records.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many records"))?;
+    let mut by_name: Vec<usize> = (0..records.len()).collect();
+    by_name.sort_by(|a, b| records[*a].name.as_bytes().cmp(records[*b].name.as_bytes()));
+    let mut by_id: Vec<(u32, usize)> = records
+        .iter()
+        .enumerate()
+        .filter_map(|(i, record)| record.id.map(|id| (id, i)))
+        .collect();
+    by_id.sort();
+    // Records are stored after both tables, in their original order
+    let name_table = HEADER_LEN;
+    let id_table = name_table + 8 * records.len() as u64;
+    let mut offsets = Vec::with_capacity(records.len());
+    let mut offset = id_table + ID_ENTRY_LEN * by_id.len() as u64;
+    for record in records {
+        offsets.push(offset);
+        offset += 8 + record.fields.iter().map(|x| 4 + x.len() as u64).sum::<u64>();
+    }
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&count.to_le_bytes())?;
+    writer.write_all(&(by_id.len() as u32).to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&name_table.to_le_bytes())?;
+    writer.write_all(&id_table.to_le_bytes())?;
+    for i in by_name {
+        writer.write_all(&offsets[i].to_le_bytes())?;
+    }
+    for (id, i) in by_id {
+        writer.write_all(&id.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&offsets[i].to_le_bytes())?;
+    }
+    for record in records {
+        writer.write_all(&(record.fields.len() as u32).to_le_bytes())?;
+        writer.write_all(&(if record.hidden { HIDDEN } else { 0 }).to_le_bytes())?;
+        for field in &record.fields {
+            let len = u32::try_from(field.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "field is too long"))?;
+            writer.write_all(&len.to_le_bytes())?;
+            writer.write_all(field.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/** An open index file */
+struct Index {
+    file: File,
+    len: u64,
+    name_count: u64,
+    id_count: u64,
+    name_table: u64,
+    id_table: u64,
+}
+
+/** Describe a problem with the layout of an index file */
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Index {
+    /** Open the index file of a database
+    - The file is only trusted if it is owned by root and not writable by other users */
+    fn open(database: &str) -> io::Result<Index> {
+        let file = File::open(index_path(database))?;
+        let metadata = file.metadata()?;
+        if !trusted(metadata.uid(), metadata.mode()) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "untrusted index file"));
+        }
+        Index::from_file(file)
+    }
+
+    /** Validate the header of an index file, and that its tables are within the file */
+    fn from_file(file: File) -> io::Result<Index> {
+        let len = file.metadata()?.len();
+        if len < HEADER_LEN {
+            return Err(invalid("index file is truncated"));
+        }
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact_at(&mut header, 0)?;
+        if &header[0..8] != MAGIC {
+            return Err(invalid("not an index file"));
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported index version {}", version),
+            ));
+        }
+        let index = Index {
+            file,
+            len,
+            name_count: u32::from_le_bytes(header[12..16].try_into().unwrap()) as u64,
+            id_count: u32::from_le_bytes(header[16..20].try_into().unwrap()) as u64,
+            name_table: u64::from_le_bytes(header[24..32].try_into().unwrap()),
+            id_table: u64::from_le_bytes(header[32..40].try_into().unwrap()),
+        };
+        index.check_range(index.name_table, 8 * index.name_count)?;
+        index.check_range(index.id_table, ID_ENTRY_LEN * index.id_count)?;
+        Ok(index)
+    }
+
+    /** Check that a range of bytes is within the file, before reading or allocating for it */
+    fn check_range(&self, offset: u64, len: u64) -> io::Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if offset >= HEADER_LEN && end <= self.len => Ok(()),
+            _ => Err(invalid("offset or length is outside of the index file")),
+        }
+    }
+
+    fn read_u32(&self, offset: u64) -> io::Result<u32> {
+        self.check_range(offset, 4)?;
+        let mut buffer = [0u8; 4];
+        self.file.read_exact_at(&mut buffer, offset)?;
+        Ok(u32::from_le_bytes(buffer))
+    }
+
+    fn read_u64(&self, offset: u64) -> io::Result<u64> {
+        self.check_range(offset, 8)?;
+        let mut buffer = [0u8; 8];
+        self.file.read_exact_at(&mut buffer, offset)?;
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    /** Read a single field, returning it with the offset of the next field */
+    fn read_field(&self, offset: u64) -> io::Result<(String, u64)> {
+        let len = self.read_u32(offset)? as u64;
+        self.check_range(offset + 4, len)?;
+        let mut buffer = vec![0u8; len as usize];
+        self.file.read_exact_at(&mut buffer, offset + 4)?;
+        match String::from_utf8(buffer) {
+            Ok(field) => Ok((field, offset + 4 + len)),
+            Err(_) => Err(invalid("field is not UTF-8")),
+        }
+    }
+
+    /** Read only the name (first field) of the record at an offset */
+    fn read_name(&self, offset: u64) -> io::Result<String> {
+        Ok(self.read_field(offset + 8)?.0)
+    }
+
+    /** Read all fields of the record at an offset, with whether it is hidden */
+    fn read_record(&self, offset: u64) -> io::Result<(Vec<String>, bool)> {
+        let count = self.read_u32(offset)? as u64;
+        let hidden = self.read_u32(offset + 4)? & HIDDEN != 0;
+        // Each field takes at least 4 bytes for its length
+        self.check_range(offset + 8, 4 * count)?;
+        let mut fields = Vec::new();
+        let mut position = offset + 8;
+        for _ in 0..count {
+            let (field, next) = self.read_field(position)?;
+            fields.push(field);
+            position = next;
+        }
+        Ok((fields, hidden))
+    }
+
+    /** Binary search the name table for the first record with a name */
+    fn find_name(&self, name: &str) -> io::Result<Option<(Vec<String>, bool)>> {
+        let (mut low, mut high) = (0, self.name_count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let offset = self.read_u64(self.name_table + 8 * middle)?;
+            if self.read_name(offset)?.as_bytes() < name.as_bytes() {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        if low < self.name_count {
+            let offset = self.read_u64(self.name_table + 8 * low)?;
+            if self.read_name(offset)? == name {
+                return Ok(Some(self.read_record(offset)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /** Binary search the id table for the first record with an id */
+    fn find_id(&self, id: u32) -> io::Result<Option<(Vec<String>, bool)>> {
+        let (mut low, mut high) = (0, self.id_count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.read_u32(self.id_table + ID_ENTRY_LEN * middle)? < id {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        if low < self.id_count && self.read_u32(self.id_table + ID_ENTRY_LEN * low)? == id {
+            let offset = self.read_u64(self.id_table + ID_ENTRY_LEN * low + 8)?;
+            return Ok(Some(self.read_record(offset)?));
+        }
+        Ok(None)
+    }
+
+    /** Read the records in name order, as described for read_all */
+    fn read_all(&self, listing: bool, max_entries: usize) -> io::Result<Vec<(Vec<String>, bool)>> {
+        let mut records = Vec::new();
+        for i in 0..self.name_count {
+            if records.len() >= max_entries {
+                break;
+            }
+            let offset = self.read_u64(self.name_table + 8 * i)?;
+            let (fields, hidden) = self.read_record(offset)?;
+            if !(listing && hidden) {
+                records.push((fields, hidden));
+            }
+        }
+        Ok(records)
+    }
+}
+
+/** Look up the fields of a single record in the index file of a database, with whether it is hidden
+- If no record matches the key, return NSS code NotFound
+- If the index file is missing or invalid, return NSS code Unavail */
+pub fn lookup(database: &str, key: Key, debug: bool) -> Response<(Vec<String>, bool)> {
+    let result = Index::open(database).and_then(|index| match key {
+        Key::Name(name) => index.find_name(name),
+        Key::Id(id) => index.find_id(id),
+    });
+    match result {
+        Ok(Some(fields)) => Response::Success(fields),
+        Ok(None) => Response::NotFound,
+        Err(e) => {
+            debug_print!(format!("Unable to read index file for {}: {}", database, e), debug);
+            Response::Unavail
+        }
+    }
+}
+
+/** Read the fields of the records in the index file of a database, in name order, with whether each is hidden
+- When listing records for enumeration, hidden records are left out, and at most max_entries are read
+- If the index file is missing or invalid, return NSS code Unavail */
+pub fn read_all(database: &str, listing: bool, max_entries: usize, debug: bool) -> Response<Vec<(Vec<String>, bool)>> {
+    let result = Index::open(database).and_then(|index| index.read_all(listing, max_entries));
+    match result {
+        Ok(records) if !records.is_empty() => Response::Success(records),
+        Ok(_) => Response::NotFound,
+        Err(e) => {
+            debug_print!(format!("Unable to read index file for {}: {}", database, e), debug);
+            Response::Unavail
+        }
+    }
+}
+
+/** Read all records in the index file of a database so that they can be updated and written again
+- If the index file is missing or invalid, return None */
+pub fn read_records(database: &str, debug: bool) -> Option<Vec<Record>> {
+    let records = match read_all(database, false, usize::MAX, debug) {
+        Response::Success(records) => records,
+        Response::NotFound => Vec::new(),
+        _ => return None,
+    };
+    Some(
+        records
+            .into_iter()
+            .map(|(fields, hidden)| Record {
+                name: fields.first().cloned().unwrap_or_default(),
+                id: id_field(database).and_then(|i| fields.get(i)).and_then(|x| x.parse().ok()),
+                hidden,
+                fields,
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    static FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    fn record(name: &str, id: Option<u32>, hidden: bool) -> Record {
+        let id_field = id.map(|x| x.to_string()).unwrap_or_default();
+        Record {
+            name: name.to_string(),
+            id,
+            hidden,
+            fields: vec![name.to_string(), "x".to_string(), id_field],
+        }
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            record("bob", Some(1002), false),
+            record("alice", Some(1001), true),
+            record("carol", None, false),
+        ]
+    }
+
+    fn serialize(records: &[Record]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_records(&mut bytes, records).unwrap();
+        bytes
+    }
+
+    /** Open bytes as an index file, through a temporary file which is removed once it is open */
+    fn open(bytes: &[u8]) -> io::Result<Index> {
+        let path = std::env::temp_dir().join(format!(
+            "libnss_shim-test-{}-{}.idx",
+            process::id(),
+            FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, bytes).unwrap();
+        let file = File::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        Index::from_file(file)
+    }
+
+    /** Offset of the first record, after the header and both tables */
+    fn first_record(bytes: &[u8]) -> usize {
+        let name_count = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let id_count = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        HEADER_LEN as usize + 8 * name_count + ID_ENTRY_LEN as usize * id_count
+    }
+
+    fn assert_invalid<T>(result: io::Result<T>) {
+        match result {
+            Ok(_) => panic!("invalid index was accepted"),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+        }
+    }
+
+    #[test]
+    fn finds_records_by_name_and_id() {
+        let index = open(&serialize(&records())).unwrap();
+        let (fields, hidden) = index.find_name("alice").unwrap().unwrap();
+        assert_eq!(fields, vec!["alice", "x", "1001"]);
+        assert!(hidden);
+        let (fields, hidden) = index.find_id(1002).unwrap().unwrap();
+        assert_eq!(fields[0], "bob");
+        assert!(!hidden);
+        assert_eq!(index.find_name("carol").unwrap().unwrap().0[0], "carol");
+        assert!(index.find_name("dave").unwrap().is_none());
+        assert!(index.find_name("").unwrap().is_none());
+        assert!(index.find_id(1003).unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_all_records_in_name_order() {
+        let index = open(&serialize(&records())).unwrap();
+        let names = |records: Vec<(Vec<String>, bool)>| -> Vec<String> {
+            records.into_iter().map(|(fields, _)| fields[0].clone()).collect()
+        };
+        assert_eq!(names(index.read_all(false, usize::MAX).unwrap()), vec!["alice", "bob", "carol"]);
+        // Hidden records are left out when listing, and do not count towards the limit
+        assert_eq!(names(index.read_all(true, usize::MAX).unwrap()), vec!["bob", "carol"]);
+        assert_eq!(names(index.read_all(true, 1).unwrap()), vec!["bob"]);
+    }
+
+    #[test]
+    fn reads_an_empty_index() {
+        let index = open(&serialize(&[])).unwrap();
+        assert!(index.find_name("alice").unwrap().is_none());
+        assert!(index.find_id(0).unwrap().is_none());
+        assert!(index.read_all(false, usize::MAX).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let bytes = serialize(&records());
+        assert_invalid(open(&bytes[..HEADER_LEN as usize - 1]));
+        assert_invalid(open(&[]));
+    }
+
+    #[test]
+    fn rejects_wrong_magic_and_version() {
+        let mut bytes = serialize(&records());
+        bytes[0] = b'X';
+        assert_invalid(open(&bytes));
+        let mut bytes = serialize(&records());
+        bytes[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_invalid(open(&bytes));
+    }
+
+    #[test]
+    fn rejects_tables_outside_the_file() {
+        let mut bytes = serialize(&records());
+        bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_invalid(open(&bytes));
+        let mut bytes = serialize(&records());
+        bytes[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_invalid(open(&bytes));
+        // Tables cannot overlap the header either
+        let mut bytes = serialize(&records());
+        bytes[24..32].copy_from_slice(&0u64.to_le_bytes());
+        assert_invalid(open(&bytes));
+    }
+
+    #[test]
+    fn rejects_fields_outside_the_file() {
+        let mut bytes = serialize(&records());
+        // The first field of the first record (bob) claims to be longer than the file
+        let offset = first_record(&bytes) + 8;
+        bytes[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let index = open(&bytes).unwrap();
+        assert_invalid(index.find_id(1002));
+        assert_invalid(index.read_all(false, usize::MAX));
+    }
+
+    #[test]
+    fn rejects_field_counts_outside_the_file() {
+        let mut bytes = serialize(&records());
+        let offset = first_record(&bytes);
+        bytes[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let index = open(&bytes).unwrap();
+        assert_invalid(index.find_id(1002));
+    }
+
+    #[test]
+    fn rejects_record_offsets_outside_the_file() {
+        let mut bytes = serialize(&records());
+        let name_table = HEADER_LEN as usize;
+        bytes[name_table..name_table + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let index = open(&bytes).unwrap();
+        assert_invalid(index.read_all(false, usize::MAX));
+    }
+
+    #[test]
+    fn rejects_truncated_records() {
+        let bytes = serialize(&records());
+        let index = open(&bytes[..bytes.len() - 1]).unwrap();
+        assert_invalid(index.read_all(false, usize::MAX));
+    }
+
+    #[test]
+    fn rejects_fields_which_are_not_utf8() {
+        let mut bytes = serialize(&records());
+        // The first byte of the name of the first record (bob)
+        let offset = first_record(&bytes) + 12;
+        bytes[offset] = 0xff;
+        let index = open(&bytes).unwrap();
+        assert_invalid(index.find_id(1002));
+    }
+}
@@ -1,24 +1,69 @@
 // Use safe Rust
+// The NSS functions that libnss does not provide are exported by the macros of nss_shim_ffi, like those of libnss
 #![forbid(unsafe_code)]
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
 use std::io::Read;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::process::Child;
 use std::process::Command;
 use std::process::Stdio;
-use std::usize;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 use libnss::group::Group;
 use libnss::group::GroupHooks;
+use libnss::host::AddressFamily;
+use libnss::host::Addresses;
+use libnss::host::Host;
+use libnss::host::HostHooks;
 use libnss::interop::Response;
-use libnss::libnss_group_hooks;
-use libnss::libnss_passwd_hooks;
-use libnss::libnss_shadow_hooks;
+use libnss::libnss_host_hooks;
 use libnss::passwd::Passwd;
 use libnss::passwd::PasswdHooks;
 use libnss::shadow::Shadow;
 use libnss::shadow::ShadowHooks;
-use shlex;
+
+use nss_shim_ffi::aliases::Alias;
+use nss_shim_ffi::aliases::AliasHooks;
+use nss_shim_ffi::gshadow::GShadow;
+use nss_shim_ffi::gshadow::GShadowHooks;
+use nss_shim_ffi::ethers::Ether;
+use nss_shim_ffi::ethers::EtherHooks;
+use nss_shim_ffi::initgroups::InitgroupsHooks;
+use nss_shim_ffi::netgroup::NetgroupEntry;
+use nss_shim_ffi::netgroup::NetgroupHooks;
+use nss_shim_ffi::networks::Network;
+use nss_shim_ffi::networks::NetworkHooks;
+use nss_shim_ffi::paged::Page;
+use nss_shim_ffi::paged::PagedHooks;
+use nss_shim_ffi::paged::Position;
+use nss_shim_ffi::protocols::Protocol;
+use nss_shim_ffi::protocols::ProtocolHooks;
+use nss_shim_ffi::rpc::Rpc;
+use nss_shim_ffi::rpc::RpcHooks;
+use nss_shim_ffi::services::Service;
+use nss_shim_ffi::services::ServiceHooks;
+use nss_shim_ffi::subid::SubidHooks;
+use nss_shim_ffi::subid::SubidKind;
+use nss_shim_ffi::subid::SubidRange;
+use nss_shim_ffi::ethers;
+use nss_shim_ffi::failure;
+use nss_shim_ffi::netgroup;
+use nss_shim_ffi::networks;
+use nss_shim_ffi::paged;
+use nss_shim_ffi::subid;
 
 //////////////
 //  Macros  //
@@ -114,12 +159,14 @@ macro_rules! validate_config {
 }
 
 /** Validate command output
+- If JSON with a status envelope, return its NSS code unless it is success
 - If JSON, set an Option with the deserialized response data
+- If JSON Lines, set an Option with the output, to be parsed line by line
 - If not JSON, set an Option with None
 - If invalid or blank, return the appropriate NSS code */
 macro_rules! validate_response {
     ( $json: ident, $option: ident, $debug: ident ) => {
-        let $option : Option<serde_json::Value>;
+        let $option : Option<JsonOutput>;
         // Check if it is actually valid formatted JSON using the fast IgnoredAny strategy
         match serde_json::from_str::<serde::de::IgnoredAny>(&$json) {
             Ok(_) => {
@@ -132,8 +179,28 @@ macro_rules! validate_response {
                         return Response::TryAgain;
                     }
                 };
+                // If there is a status envelope, return its NSS code or continue with its entries
+                let deser = match open_envelope(deser, $debug) {
+                    Ok(deser) => deser,
+                    Err(response) => return failure(response),
+                };
+                // Arrays of records are also accepted, if every record has a name
+                if let Some(records) = deser.as_array() {
+                    if records.is_empty() {
+                        debug_print!(format!("Nothing found in JSON: {}", &$json), $debug);
+                        return Response::NotFound;
+                    }
+                    if !is_json_records(&deser) {
+                        debug_print!(format!("JSON array has records without a name: {}", &$json), $debug);
+                        return Response::TryAgain;
+                    }
+                }
                 // Check first if it is a valid object with expected structure (object at root with str key)
                 match deser.as_object() {
+                    // A single record with a name is JSON Lines with one line
+                    Some(_) if is_json_record(&deser) => {
+                        $option = Some(JsonOutput::Lines(&$json));
+                    }
                     Some(result) => {
                         // If it is valid but has an empty response, that means nothing was found
                         if result.len() < 1 {
@@ -149,7 +216,10 @@ macro_rules! validate_response {
                             }
                         };
                         // It is valid, so set the deserialized JSON as the Option contents
-                        $option = Some(deser);
+                        $option = Some(JsonOutput::Document(deser));
+                    }
+                    _ if deser.is_array() => {
+                        $option = Some(JsonOutput::Document(deser));
                     }
                     _ => {
                         // The JSON is not formatted as expected
@@ -158,6 +228,10 @@ macro_rules! validate_response {
                     }
                 };
             }
+            // Each line is a JSON record, so they are parsed line by line
+            Err(_) if is_json_lines(&$json) => {
+                $option = Some(JsonOutput::Lines(&$json));
+            }
             Err(e) => {
                 // This is not valid JSON, so assume it is in passwd type string format
                 // If it has less than 4 chars after trimming whitespace, then it is considered NotFound
@@ -172,19 +246,34 @@ macro_rules! validate_response {
     }
 }
 
+/** Unwrap a record given by JsonOutput::records
+- If it is from an invalid line of JSON Lines, return NSS code TryAgain */
+macro_rules! valid_record {
+    ( $record: ident, $debug: ident ) => {
+        match $record {
+            Ok(record) => record,
+            Err(e) => {
+                debug_print!(e, $debug);
+                return Response::TryAgain;
+            }
+        }
+    };
+}
+
 /** Parse the config JSON for a specific function
 - If the expected function/database is not present, return NSS code NotFound
 - If the function is there but no command is defined, return NSS code Unavail
 - If the command is not valid/parseable, return NSS code Unavail
-- If all checks pass, return a Vec of command/args, a Map of env vars, and a working directory */
+- If the timeout is not a positive number of seconds, return NSS code Unavail
+- If all checks pass, return a Vec of command/args, a Map of env vars, a working directory, and an optional timeout */
 macro_rules! parse_config {
-    ($config_deser: ident, $target_db: ident, $target_function: ident, $command: ident, $env_vars: ident, $dir: ident, $debug: ident ) => {
+    ($config_deser: ident, $target_db: ident, $target_function: ident, $command: ident, $env_vars: ident, $dir: ident, $timeout: ident, $debug: ident ) => {
         // Define the 'path' in the json to get to the command from the root
         let command_path: [String; 5] = [
             "databases".to_string(),
-            $target_db,
+            $target_db.clone(),
             "functions".to_string(),
-            $target_function,
+            $target_function.clone(),
            "command".to_string()
         ];
         // Define the indexes of the levels in command_path to check for env/workdir
@@ -260,7 +349,7 @@ macro_rules! parse_config {
                             }
                         }
                     }
-                    if $dir == "".to_string() && level_json.as_object().unwrap().keys().any(|x| x == "workdir"){
+                    if $dir.is_empty() && level_json.as_object().unwrap().keys().any(|x| x == "workdir"){
                         match level_json["workdir"].as_str() {
                             Some(workdir_string) => {
                                 $dir = workdir_string.to_string();
@@ -273,7 +362,7 @@ macro_rules! parse_config {
                         }
                     }
                     // If they've both been set, stop looking
-                    if $env_vars.len() > 0 && $dir != "".to_string() {
+                    if !$env_vars.is_empty() && !$dir.is_empty() {
                         break;
                     }
                 }
@@ -294,15 +383,38 @@ macro_rules! parse_config {
                 };
             };
         }
+        // Check for a custom 'timeout' at the function, database, and global levels
+        let $timeout = match find_setting(&$config_deser, &$target_db, &$target_function, "timeout") {
+            Some(timeout) => match timeout.as_f64() {
+                Some(secs) if secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+                _ => {
+                    // A positive number of seconds is expected
+                    debug_print!(format!("timeout in config.json must be a positive number"), $debug);
+                    return Response::Unavail;
+                }
+            },
+            None => None,
+        };
     }
 }
 
+/** Find the options for parsing the entries of a database in command output, with parse_options
+- If any of the `validation`, `defaults`, or `escaped_colons` settings is invalid, return NSS code Unavail */
+macro_rules! parse_options {
+    ($config_deser: ident, $target_db: ident, $target_function: ident, $options: ident, $debug: ident) => {
+        let $options = match parse_options(&$config_deser, &$target_db, &$target_function, $debug) {
+            Ok(options) => options,
+            Err(()) => return Response::Unavail,
+        };
+    };
+}
+
 /** Parse a line in /etc/group format into a Group object
 - If invalid, return the appropriate NSS code
 - If missing entries, they will be replaced with blanks */
 macro_rules! parse_group_format {
-    ( $entries: ident, $debug: ident ) => {
-        Group {
+    ( $entries: ident, $validation: expr, $defaults: expr, $debug: ident ) => {{
+        let mut group = Group {
             name: match $entries.next() {
                 Some(s) => s.to_string(),
                 _ => {
@@ -314,7 +426,7 @@ macro_rules! parse_group_format {
                 Some(s) => s.to_string(),
                 _ => "".to_string(),
             },
-            gid: match $entries.next().and_then(|s| s.parse().ok()) {
+            gid: match format_number($entries.next(), $validation) {
                 Some(s) => s,
                 _ => {
                     debug_print!(format!("Unable to parse gid for group"), $debug);
@@ -324,7 +436,8 @@ macro_rules! parse_group_format {
             members: match $entries.next() {
                 Some(s) => {
                     let mut members: Vec<String> = Vec::new();
-                    for user in s.split(",") {
+                    // Skip empty names so that a blank field means no members
+                    for user in s.split(",").filter(|x| !x.is_empty()) {
                         members.push(user.to_string());
                     }
                     members
@@ -334,16 +447,18 @@ macro_rules! parse_group_format {
                     members
                 }
             },
-        }
-    };
+        };
+        group_defaults(&mut group, $defaults);
+        group
+    }};
 }
 
 /** Parse a line in /etc/passwd format into a Passwd object
 - If invalid, return the appropriate NSS code
 - If missing entries, they will be replaced with blanks */
 macro_rules! parse_passwd_format {
-    ( $ entries: ident, $debug: ident ) => {
-        Passwd {
+    ( $ entries: ident, $validation: expr, $defaults: expr, $debug: ident ) => {{
+        let mut passwd = Passwd {
             name: match $entries.next() {
                 Some(s) => s.to_string(),
                 _ => {
@@ -355,14 +470,14 @@ macro_rules! parse_passwd_format {
                 Some(s) => s.to_string(),
                 _ => "".to_string(),
             },
-            uid: match $entries.next().and_then(|s| s.parse().ok()) {
+            uid: match format_number($entries.next(), $validation) {
                 Some(s) => s,
                 _ => {
                     debug_print!(format!("Unable to parse uid for passwd"), $debug);
                     return Response::TryAgain;
                 }
             },
-            gid: match $entries.next().and_then(|s| s.parse().ok()) {
+            gid: match format_number($entries.next(), $validation) {
                 Some(s) => s,
                 _ => {
                     debug_print!(format!("Unable to parse gid for passwd"), $debug);
@@ -381,16 +496,22 @@ macro_rules! parse_passwd_format {
                 Some(s) => s.to_string(),
                 _ => "".to_string(),
             },
+        };
+        passwd_defaults(&mut passwd, $defaults);
+        if let Some(field) = missing_passwd_field(&passwd, $validation) {
+            debug_print!(format!("Missing {} for passwd: {}", field, passwd.name), $debug);
+            return Response::TryAgain;
         }
-    };
+        passwd
+    }};
 }
 
 /** Parse a line in /etc/shadow format into a Shadow object
 - If invalid, return the appropriate NSS code
 - If missing entries, they will be replaced with blanks */
 macro_rules! parse_shadow_format {
-    ( $entries: ident, $debug: ident ) => {
-        Shadow {
+    ( $entries: ident, $validation: expr, $defaults: expr, $debug: ident ) => {{
+        let mut shadow = Shadow {
             name: match $entries.next() {
                 Some(s) => s.to_string(),
                 _ => {
@@ -402,34 +523,113 @@ macro_rules! parse_shadow_format {
                 Some(s) => s.to_string(),
                 _ => "".to_string(),
             },
-            last_change: match $entries.next().and_then(|s| s.parse().ok()) {
-                Some(s) => s,
-                _ => -1,
+            last_change: match format_optional_number($entries.next(), -1, $validation) {
+                Ok(s) => s,
+                Err(s) => {
+                    debug_print!(format!("Unable to parse last_change: {} for shadow", s), $debug);
+                    return Response::TryAgain;
+                }
             },
-            change_min_days: match $entries.next().and_then(|s| s.parse().ok()) {
-                Some(s) => s,
-                _ => -1,
+            change_min_days: match format_optional_number($entries.next(), -1, $validation) {
+                Ok(s) => s,
+                Err(s) => {
+                    debug_print!(format!("Unable to parse change_min_days: {} for shadow", s), $debug);
+                    return Response::TryAgain;
+                }
             },
-            change_max_days: match $entries.next().and_then(|s| s.parse().ok()) {
-                Some(s) => s,
-                _ => -1,
+            change_max_days: match format_optional_number($entries.next(), -1, $validation) {
+                Ok(s) => s,
+                Err(s) => {
+                    debug_print!(format!("Unable to parse change_max_days: {} for shadow", s), $debug);
+                    return Response::TryAgain;
+                }
             },
-            change_warn_days: match $entries.next().and_then(|s| s.parse().ok()) {
-                Some(s) => s,
-                _ => -1,
+            change_warn_days: match format_optional_number($entries.next(), -1, $validation) {
+                Ok(s) => s,
+                Err(s) => {
+                    debug_print!(format!("Unable to parse change_warn_days: {} for shadow", s), $debug);
+                    return Response::TryAgain;
+                }
             },
-            change_inactive_days: match $entries.next().and_then(|s| s.parse().ok()) {
-                Some(s) => s,
-                _ => -1,
+            change_inactive_days: match format_optional_number($entries.next(), -1, $validation) {
+                Ok(s) => s,
+                Err(s) => {
+                    debug_print!(format!("Unable to parse change_inactive_days: {} for shadow", s), $debug);
+                    return Response::TryAgain;
+                }
             },
-            expire_date: match $entries.next().and_then(|s| s.parse().ok()) {
-                Some(s) => s,
-                _ => -1,
+            expire_date: match format_optional_number($entries.next(), -1, $validation) {
+                Ok(s) => s,
+                Err(s) => {
+                    debug_print!(format!("Unable to parse expire_date: {} for shadow", s), $debug);
+                    return Response::TryAgain;
+                }
             },
-            reserved: match $entries.next().and_then(|s| s.parse().ok()) {
-                Some(s) => s,
-                _ => usize::MAX,
+            reserved: match format_optional_number($entries.next(), usize::MAX, $validation) {
+                Ok(s) => s,
+                Err(s) => {
+                    debug_print!(format!("Unable to parse reserved: {} for shadow", s), $debug);
+                    return Response::TryAgain;
+                }
+            },
+        };
+        shadow_defaults(&mut shadow, $defaults);
+        shadow
+    }};
+}
+
+/** Parse a line in /etc/gshadow format into a GShadow object
+- If invalid, return the appropriate NSS code
+- If missing entries, they will be replaced with blanks */
+macro_rules! parse_gshadow_format {
+    ( $entries: ident, $validation: expr, $defaults: expr, $debug: ident ) => {{
+        let mut gshadow = GShadow {
+            name: match $entries.next() {
+                Some(s) => s.to_string(),
+                _ => {
+                    debug_print!(format!("Unable to parse name for gshadow"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            passwd: match $entries.next() {
+                Some(s) => s.to_string(),
+                _ => "".to_string(),
+            },
+            administrators: match $entries.next() {
+                Some(s) => s.split(",").filter(|x| !x.is_empty()).map(|x| x.to_string()).collect(),
+                _ => Vec::new(),
+            },
+            members: match $entries.next() {
+                Some(s) => s.split(",").filter(|x| !x.is_empty()).map(|x| x.to_string()).collect(),
+                _ => Vec::new(),
+            },
+        };
+        gshadow_defaults(&mut gshadow, $defaults);
+        gshadow
+    }};
+}
+
+/** Parse a line in /etc/hosts format (address, name, then aliases) into a Host object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_host_format {
+    ( $entries: ident, $debug: ident ) => {
+        Host {
+            addresses: match $entries.next().and_then(|s| s.parse().ok()) {
+                Some(IpAddr::V4(x)) => Addresses::V4(vec![x]),
+                Some(IpAddr::V6(x)) => Addresses::V6(vec![x]),
+                _ => {
+                    debug_print!(format!("Unable to parse address for hosts"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            name: match $entries.next() {
+                Some(s) => s.to_string(),
+                _ => {
+                    debug_print!(format!("Unable to parse name for hosts"), $debug);
+                    return Response::TryAgain;
+                }
             },
+            aliases: $entries.map(|x| x.to_string()).collect(),
         }
     };
 }
@@ -438,37 +638,44 @@ macro_rules! parse_shadow_format {
 - If invalid, return the appropriate NSS code
 - If missing entries, they will be replaced with blanks */
 macro_rules! parse_group_json {
-    ( $group_entry: ident, $deser: ident, $group: ident, $debug: ident ) => {
+    ( $group_entry: ident, $record: ident, $group: ident, $validation: expr, $defaults: expr, $debug: ident ) => {
+        let $record = match checked_record(&$record, "group", $validation) {
+            Ok(x) => x,
+            Err(field) => {
+                debug_print!(format!("Unknown field: {} for group JSON", field), $debug);
+                return Response::TryAgain;
+            }
+        };
         let name = $group_entry.to_string();
         let passwd;
-        if $deser[$group_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "passwd")
         {
-            passwd = $deser[$group_entry]["passwd"]
+            passwd = $record["passwd"]
                 .as_str()
                 .unwrap_or_else(|| "")
                 .to_string();
         } else {
             passwd = "".to_string();
         }
-        let gid = match $deser[$group_entry]["gid"].as_u64() {
+        let gid = match json_id(&$record["gid"], $validation) {
             Some(x) => x,
             _ => {
                 debug_print!(format!("Unable to parse gid for group JSON"), $debug);
                 return Response::TryAgain;
             }
-        } as u32;
+        };
         let mut members: Vec<String> = Vec::new();
-        if $deser[$group_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "members")
         {
-            for member in match $deser[$group_entry]["members"].as_array() {
+            for member in match $record["members"].as_array() {
                 Some(x) => x,
                 _ => {
                     debug_print!(
@@ -493,7 +700,8 @@ macro_rules! parse_group_json {
                 );
             }
         }
-        let $group = Group {name, passwd, gid, members};
+        let mut $group = Group {name, passwd, gid, members};
+        group_defaults(&mut $group, $defaults);
     };
 }
 
@@ -501,16 +709,23 @@ macro_rules! parse_group_json {
 - If invalid, return the appropriate NSS code
 - If missing entries, they will be replaced with blanks */
 macro_rules! parse_passwd_json {
-    ( $passwd_entry: ident, $deser: ident, $passwd: ident, $debug: ident ) => {
+    ( $passwd_entry: ident, $record: ident, $passwd: ident, $validation: expr, $defaults: expr, $debug: ident ) => {
+        let $record = match checked_record(&$record, "passwd", $validation) {
+            Ok(x) => x,
+            Err(field) => {
+                debug_print!(format!("Unknown field: {} for passwd JSON", field), $debug);
+                return Response::TryAgain;
+            }
+        };
         let name = $passwd_entry.to_string();
         let mut passwd = "".to_string();
-        if $deser[$passwd_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "passwd")
         {
-            passwd = match $deser[$passwd_entry]["passwd"].as_str() {
+            passwd = match $record["passwd"].as_str() {
                 Some(x) => x,
                 _ => {
                     debug_print!(
@@ -522,29 +737,37 @@ macro_rules! parse_passwd_json {
             }
             .to_string();
         }
-        let uid = match $deser[$passwd_entry]["uid"].as_u64() {
+        let uid = match json_id(&$record["uid"], $validation) {
             Some(x) => x,
             _ => {
                 debug_print!(format!("Unable to parse uid for passwd JSON"), $debug);
                 return Response::TryAgain;
             }
-        } as u32;
-        let gid = match $deser[$passwd_entry]["gid"].as_u64() {
+        };
+        let gid = match json_id(&$record["gid"], $validation) {
             Some(x) => x,
             _ => {
                 debug_print!(format!("Unable to parse gid for passwd JSON"), $debug);
                 return Response::TryAgain;
             }
-        } as u32;
+        };
         let mut gecos = "".to_string();
-        if $deser[$passwd_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "gecos")
         {
-            gecos = match $deser[$passwd_entry]["gecos"].as_str() {
-                Some(x) => x,
+            gecos = match &$record["gecos"] {
+                serde_json::Value::String(x) => x.to_string(),
+                // Assemble the comma-separated string from its parts
+                serde_json::Value::Object(parts) => match gecos_from_parts(parts) {
+                    Ok(x) => x,
+                    Err(part) => {
+                        debug_print!(format!("Unable to parse gecos part: {} for passwd JSON", part), $debug);
+                        return Response::TryAgain;
+                    }
+                },
                 _ => {
                     debug_print!(
                         format!("Unable to parse gecos: {} for passwd JSON", gecos),
@@ -552,17 +775,16 @@ macro_rules! parse_passwd_json {
                     );
                     return Response::TryAgain;
                 }
-            }
-            .to_string();
+            };
         }
         let mut dir = "".to_string();
-        if $deser[$passwd_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "dir")
         {
-            dir = match $deser[$passwd_entry]["dir"].as_str() {
+            dir = match $record["dir"].as_str() {
                 Some(x) => x,
                 _ => {
                     debug_print!(
@@ -575,13 +797,13 @@ macro_rules! parse_passwd_json {
             .to_string();
         }
         let mut shell = "".to_string();
-        if $deser[$passwd_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "shell")
         {
-            shell = match $deser[$passwd_entry]["shell"].as_str() {
+            shell = match $record["shell"].as_str() {
                 Some(x) => x,
                 _ => {
                     debug_print!(
@@ -593,7 +815,12 @@ macro_rules! parse_passwd_json {
             }
             .to_string();
         }
-        let $passwd = Passwd {name, passwd, uid, gid, gecos, dir, shell};
+        let mut $passwd = Passwd {name, passwd, uid, gid, gecos, dir, shell};
+        passwd_defaults(&mut $passwd, $defaults);
+        if let Some(field) = missing_passwd_field(&$passwd, $validation) {
+            debug_print!(format!("Missing {} for passwd JSON: {}", field, $passwd.name), $debug);
+            return Response::TryAgain;
+        }
     };
 }
 
@@ -601,16 +828,23 @@ macro_rules! parse_passwd_json {
 - If invalid, return the appropriate NSS code
 - If missing entries, they will be replaced with blanks */
 macro_rules! parse_shadow_json {
-    ( $shadow_entry: ident, $deser: ident, $shadow: ident , $debug: ident) => {
+    ( $shadow_entry: ident, $record: ident, $shadow: ident, $validation: expr, $defaults: expr, $debug: ident) => {
+        let $record = match checked_record(&$record, "shadow", $validation) {
+            Ok(x) => x,
+            Err(field) => {
+                debug_print!(format!("Unknown field: {} for shadow JSON", field), $debug);
+                return Response::TryAgain;
+            }
+        };
         let name = $shadow_entry.to_string();
         let mut passwd = "".to_string();
-        if $deser[$shadow_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "passwd")
         {
-            passwd = match $deser[$shadow_entry]["passwd"].as_str() {
+            passwd = match $record["passwd"].as_str() {
                 Some(x) => x,
                 _ => {
                     debug_print!(
@@ -623,13 +857,13 @@ macro_rules! parse_shadow_json {
             .to_string();
         }
         let mut last_change = -1;
-        if $deser[$shadow_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "last_change")
         {
-            last_change = match $deser[$shadow_entry]["last_change"].as_i64() {
+            last_change = match $record["last_change"].as_i64() {
                 Some(x) => match x.try_into(){
                     Ok(y) => { y },
                     Err(e) => {
@@ -656,13 +890,13 @@ macro_rules! parse_shadow_json {
             };
         }
         let mut change_min_days = -1;
-        if $deser[$shadow_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "change_min_days")
         {
-            change_min_days = match $deser[$shadow_entry]["change_min_days"].as_i64() {
+            change_min_days = match $record["change_min_days"].as_i64() {
                 Some(x) => match x.try_into(){
                     Ok(y) => { y },
                     Err(e) => {
@@ -689,13 +923,13 @@ macro_rules! parse_shadow_json {
             };
         }
         let mut change_max_days = -1;
-        if $deser[$shadow_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "change_max_days")
         {
-            change_max_days = match $deser[$shadow_entry]["change_max_days"].as_i64() {
+            change_max_days = match $record["change_max_days"].as_i64() {
                 Some(x) => match x.try_into(){
                     Ok(y) => { y },
                     Err(e) => {
@@ -722,13 +956,13 @@ macro_rules! parse_shadow_json {
             };
         }
         let mut change_warn_days = -1;
-        if $deser[$shadow_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "change_warn_days")
         {
-            change_warn_days = match $deser[$shadow_entry]["change_warn_days"].as_i64() {
+            change_warn_days = match $record["change_warn_days"].as_i64() {
                 Some(x) => match x.try_into(){
                     Ok(y) => { y },
                     Err(e) => {
@@ -755,13 +989,13 @@ macro_rules! parse_shadow_json {
             };
         }
         let mut change_inactive_days = -1;
-        if $deser[$shadow_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "change_inactive_days")
         {
-            change_inactive_days = match $deser[$shadow_entry]["change_inactive_days"].as_i64() {
+            change_inactive_days = match $record["change_inactive_days"].as_i64() {
                 Some(x) => match x.try_into(){
                     Ok(y) => { y },
                     Err(e) => {
@@ -788,13 +1022,13 @@ macro_rules! parse_shadow_json {
             };
         }
         let mut expire_date = -1;
-        if $deser[$shadow_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "expire_date")
         {
-            expire_date = match $deser[$shadow_entry]["expire_date"].as_i64() {
+            expire_date = match $record["expire_date"].as_i64() {
                 Some(x) => match x.try_into(){
                     Ok(y) => { y },
                     Err(e) => {
@@ -821,13 +1055,13 @@ macro_rules! parse_shadow_json {
             };
         }
         let mut reserved = usize::MAX;
-        if $deser[$shadow_entry]
+        if $record
             .as_object()
             .unwrap()
             .keys()
             .any(|x| x == "reserved")
         {
-            reserved = match $deser[$shadow_entry]["reserved"].as_u64() {
+            reserved = match $record["reserved"].as_u64() {
                 Some(x) => match x.try_into(){
                     Ok(y) => { y },
                     Err(e) => {
@@ -850,7 +1084,7 @@ macro_rules! parse_shadow_json {
                 }
             };
         }
-        let $shadow = Shadow {
+        let mut $shadow = Shadow {
             name,
             passwd,
             last_change,
@@ -861,485 +1095,4363 @@ macro_rules! parse_shadow_json {
             expire_date,
             reserved,
         };
+        shadow_defaults(&mut $shadow, $defaults);
     };
 }
 
-/** Run the command&args with the given workdir and env vars.
-- If the code tuple has non-empty strings, instances of the first will be replaced by the second in args and env vars
-- If the command has a runtime error, return NSS code TryAgain
-- If the command succeeds, return the trimmed output */
-macro_rules! run_command_capture_output {
-    ($command: ident, $env_vars: ident, $dir: ident, $code: ident, $output: ident, $debug: ident) => {
-        // Create the runnable command using the first item in the args (i.e. the base command to run)
-        let mut runnable_command = Command::new(&$command[0]);
-        // Add arguments to the command by iterating through and replacing any of the special codes
-        for (i, arg) in $command.iter().enumerate() {
-            if i > 0 {
-                runnable_command.arg(str::replace(arg, &$code.0, &$code.1));
+/** Parse a JSON gshadow object
+- If invalid, return the appropriate NSS code
+- If missing entries, they will be replaced with blanks */
+macro_rules! parse_gshadow_json {
+    ( $gshadow_entry: ident, $record: ident, $gshadow: ident, $validation: expr, $defaults: expr, $debug: ident ) => {
+        let $record = match checked_record(&$record, "gshadow", $validation) {
+            Ok(x) => x,
+            Err(field) => {
+                debug_print!(format!("Unknown field: {} for gshadow JSON", field), $debug);
+                return Response::TryAgain;
             }
-        }
-        // Add environment variables to the command by iterating through and replacing any of the special codes
-        for env_var in $env_vars.keys() {
-            runnable_command.env(str::replace(env_var, &$code.0, &$code.1), str::replace(&$env_vars[env_var], &$code.0, &$code.1));
-        }
-        // Set working directory for running command, if present
-        if $dir.len() > 0 {
-            runnable_command.current_dir($dir);
-        }
-        // Run the command and capture the output, returning NSS code TryAgain if something goes wrong
-        let $output = match String::from_utf8(
-            match runnable_command
-                .stdout(Stdio::piped())
-                .output() {
-                Ok(x) => x.stdout,
+        };
+        let name = $gshadow_entry.to_string();
+        let passwd = match $record.get("passwd") {
+            Some(x) => match x.as_str() {
+                Some(x) => x.to_string(),
                 _ => {
-                    debug_print!(format!("Runtime error for command"), $debug);
-                    return Response::TryAgain
+                    debug_print!(format!("Unable to parse passwd for gshadow JSON"), $debug);
+                    return Response::TryAgain;
                 }
-            }
-        ) {
-            Ok(mut x) => {
-                x = x.trim().to_string();
-                x
-            }
-            _ => {
-                debug_print!(format!("Unable to capture output from command as string"), $debug);
-                return Response::TryAgain
-            }
+            },
+            _ => "".to_string(),
         };
+        let mut lists: [Vec<String>; 2] = [Vec::new(), Vec::new()];
+        for (i, key) in ["administrators", "members"].iter().enumerate() {
+            if let Some(x) = $record.get(key) {
+                for user in match x.as_array() {
+                    Some(x) => x,
+                    _ => {
+                        debug_print!(format!("Unable to parse {} for gshadow JSON", key), $debug);
+                        return Response::TryAgain;
+                    }
+                } {
+                    lists[i].push(
+                        match user.as_str() {
+                            Some(x) => x,
+                            _ => {
+                                debug_print!(format!("Unable to parse {} for gshadow JSON", key), $debug);
+                                return Response::TryAgain;
+                            }
+                        }
+                        .to_string(),
+                    );
+                }
+            }
+        }
+        let [administrators, members] = lists;
+        let mut $gshadow = GShadow {name, passwd, administrators, members};
+        gshadow_defaults(&mut $gshadow, $defaults);
     };
 }
 
-///////////////////
-//  Group hooks  //
-///////////////////
-
-struct ShimGroup;
-libnss_group_hooks!(shim, ShimGroup);
-
-impl GroupHooks for ShimGroup {
-    fn get_all_entries() -> Response<Vec<Group>> {
-        // Ensure the configuration is usable
-        validate_config!(config_deser, debug);
-        // Get the command data for this particular db and function
-        let group = "group".to_string();
-        let function = "get_all_entries".to_string();
-        parse_config!(config_deser, group, function, command, env_vars, dir, debug);
-        // Since this function does not have a uid/gid/name parameter, set the code as blank
-        let code = ("".to_string(), "".to_string());
-        // Run the command defined in the config and capture the output as String
-        run_command_capture_output!(command, env_vars, dir, code, output, debug);
-        // Validate the command output and determine if it is JSON or not
-        validate_response!(output, option, debug);
-        let mut group_vec: Vec<Group> = Vec::new();
-        match option {
-            Some(deser) => {
-                // Parse as JSON
-                for group_entry in deser.as_object().unwrap().keys() {
-                    parse_group_json!(group_entry, deser, group, debug);
-                    group_vec.push(group);
-                }
-            }
+/** Parse a JSON hosts object into one Host object per address family
+- If invalid, return the appropriate NSS code
+- If aliases are missing, they will be replaced with blanks */
+macro_rules! parse_host_json {
+    ( $host_entry: ident, $record: ident, $hosts: ident, $debug: ident ) => {
+        let name = $host_entry.to_string();
+        parse_json_strings!($record.get("aliases"), aliases, "aliases for hosts JSON", $debug);
+        let mut addresses: Vec<IpAddr> = Vec::new();
+        for address in match $record.get("addresses").and_then(|x| x.as_array()) {
+            Some(x) => x,
             _ => {
-                // Parse as unix-style file format
-                for line in output.trim().lines() {
-                    if line.matches(":").count() < 3 {
-                        debug_print!(
-                            format!(
-                                "Returned group data: {} does not match expected unix form",
-                                line
-                            ),
-                            debug
-                        );
-                        return Response::TryAgain;
-                    }
-                    let mut entries = line.trim().splitn(4, ':');
-                    group_vec.push(parse_group_format!(entries, debug));
-                }
+                debug_print!(format!("Unable to parse addresses for hosts JSON"), $debug);
+                return Response::TryAgain;
             }
-        };
-        // Shouldn't ever be 0, but good to check
-        if group_vec.len() > 0 {
-            return Response::Success(group_vec);
+        } {
+            addresses.push(match address.as_str().and_then(|x| x.parse().ok()) {
+                Some(x) => x,
+                _ => {
+                    debug_print!(format!("Unable to parse address: {} for hosts JSON", address), $debug);
+                    return Response::TryAgain;
+                }
+            });
         }
-        debug_print!("Returned group data is invalid".to_string(), debug);
-        return Response::TryAgain;
-    }
+        let $hosts = host_entries(name, aliases, addresses);
+    };
+}
 
-    fn get_entry_by_gid(gid: libc::gid_t) -> Response<Group> {
-        validate_config!(config_deser, debug);
-        let database = "group".to_string();
-        let function = "get_entry_by_gid".to_string();
-        parse_config!(config_deser, database, function, command, env_vars, dir, debug);
-        // Set the code for gid
-        let code = ("<$gid>".to_string(), gid.to_string());
-        run_command_capture_output!(command, env_vars, dir, code, output, debug);
-        validate_response!(output, option, debug);
-        match option {
-            Some(deser) => {
-                // Return after first loop iteration since this is supposed to be a single entry
-                for group_entry in deser.as_object().unwrap().keys() {
-                    if gid != match deser[group_entry]["gid"].as_u64() {
+/** Parse an optional JSON list of strings, such as aliases
+- If it is not a list of strings, return NSS code TryAgain
+- If it is missing, it will be replaced with a blank list */
+macro_rules! parse_json_strings {
+    ( $value: expr, $strings: ident, $description: expr, $debug: ident ) => {
+        let mut $strings: Vec<String> = Vec::new();
+        if let Some(x) = $value {
+            for string in match x.as_array() {
+                Some(x) => x,
+                _ => {
+                    debug_print!(format!("Unable to parse {}", $description), $debug);
+                    return Response::TryAgain;
+                }
+            } {
+                $strings.push(
+                    match string.as_str() {
                         Some(x) => x,
                         _ => {
-                            debug_print!(
-                                format!(
-                                    "Returned group data: {} does not contain a valid gid",
-                                    deser
-                                ),
-                                debug
-                            );
+                            debug_print!(format!("Unable to parse {}", $description), $debug);
                             return Response::TryAgain;
                         }
-                    } as u32
-                    {
-                        debug_print!(
-                            format!(
-                                "Returned group data: {} does not contain a matching gid: {}",
-                                deser,
-                                gid
-                            ),
-                            debug
-                        );
-                        return Response::TryAgain;
-                    }
-                    parse_group_json!(group_entry, deser, group, debug);
-                    return Response::Success(group);
-                }
-            }
-            _ => {
-                for line in output.trim().lines() {
-                    if line.matches(":").count() < 3 {
-                        debug_print!(
-                            format!(
-                                "Returned group data: {} does not match expected unix form",
-                                line
-                            ),
-                            debug
-                        );
-                        return Response::TryAgain;
                     }
-                    let mut entries = line.trim().splitn(4, ':');
-                    return Response::Success(parse_group_format!(entries, debug));
-                }
-                // Shouldn't happen since this implies the text had no lines
-                debug_print!(
-                    "Returned group data does not contain valid strings".to_string(),
-                    debug
+                    .to_string(),
                 );
-                return Response::TryAgain;
             }
-        };
-        debug_print!(format!("gid: {} not found in group", gid), debug);
-        return Response::NotFound;
-    }
+        }
+    };
+}
 
-    fn get_entry_by_name(name: String) -> Response<Group> {
-        validate_config!(config_deser, debug);
-        let database = "group".to_string();
-        let function = "get_entry_by_name".to_string();
-        parse_config!(config_deser, database, function, command, env_vars, dir, debug);
-        let code = ("<$name>".to_string(), name.clone());
-        run_command_capture_output!(command, env_vars, dir, code, output, debug);
-        validate_response!(output, option, debug);
+/** Parse the output of a command for a database in whitespace-separated file format (like /etc/hosts), or JSON
+- Each JSON entry can be parsed into more than one object, and each line is parsed into a single object
+- Comments starting with `#` and blank lines are ignored
+- With a hidden ident, the positions of entries from JSON records with `"hidden": true` are collected in a HashSet
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_whitespace_entries {
+    ( $output: ident, $entry_vec: ident, $parse_json: ident, $parse_format: ident, $debug: ident ) => {
+        parse_whitespace_entries!($output, $entry_vec, _hidden, $parse_json, $parse_format, $debug);
+    };
+    ( $output: ident, $entry_vec: ident, $hidden: ident, $parse_json: ident, $parse_format: ident, $debug: ident ) => {
+        validate_response!($output, option, $debug);
+        let mut $entry_vec = Vec::new();
+        let mut $hidden: HashSet<usize> = HashSet::new();
         match option {
             Some(deser) => {
-                for group_entry in deser.as_object().unwrap().keys() {
-                    if code.1 != group_entry.to_string() {
-                        debug_print!(
-                            format!(
-                                "Requested name: {} does not match returned name: {}",
-                                code.1,
-                                group_entry.to_string()
-                            ),
-                            debug
-                        );
-                        return Response::TryAgain;
+                for record in deser.records() {
+                    let (json_entry, record) = valid_record!(record, $debug);
+                    $parse_json!(json_entry, record, parsed, $debug);
+                    if is_hidden(&record) {
+                        $hidden.extend($entry_vec.len()..$entry_vec.len() + parsed.len());
                     }
-                    parse_group_json!(group_entry, deser, group, debug);
-                    return Response::Success(group);
+                    $entry_vec.extend(parsed);
                 }
             }
             _ => {
-                for line in output.trim().lines() {
-                    if line.matches(":").count() < 3 {
-                        debug_print!(
-                            format!(
-                                "Returned group data: {} does not match expected unix form",
-                                line
-                            ),
-                            debug
-                        );
-                        return Response::TryAgain;
+                for line in $output.trim().lines() {
+                    let line = line.split('#').next().unwrap_or("").trim();
+                    if line.is_empty() {
+                        continue;
                     }
-                    let mut entries = line.trim().splitn(4, ':');
-                    return Response::Success(parse_group_format!(entries, debug));
+                    let mut entries = line.split_whitespace();
+                    $entry_vec.push($parse_format!(entries, $debug));
                 }
-                debug_print!(
-                    format!(
-                        "Returned group data does not match expected unix form: {}",
-                        output.trim()
-                    ),
-                    debug
-                );
-                return Response::TryAgain;
             }
         };
-        debug_print!(format!("Name: {} not found in group", name), debug);
-        return Response::NotFound;
+    };
+}
+
+/** Parse the output of a hosts command, in either JSON or /etc/hosts format
+- Entries with the same name and address family are combined
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_hosts {
+    ( $output: ident, $hosts: ident, $debug: ident ) => {
+        parse_hosts!($output, $hosts, _hidden, $debug);
+    };
+    ( $output: ident, $hosts: ident, $hidden: ident, $debug: ident ) => {
+        parse_whitespace_entries!($output, host_vec, hidden_positions, parse_host_json, parse_host_format, $debug);
+        // Records for the same host are merged, so a host is only hidden if every record for it is hidden
+        let mut $hidden: HashSet<String> = HashSet::new();
+        for (i, host) in host_vec.iter().enumerate() {
+            if hidden_positions.contains(&i) {
+                $hidden.insert(host.name.clone());
+            }
+        }
+        for (i, host) in host_vec.iter().enumerate() {
+            if !hidden_positions.contains(&i) {
+                $hidden.remove(&host.name);
+            }
+        }
+        let $hosts = merge_hosts(host_vec);
+    };
+}
+
+/** Parse a line in /etc/services format (name, port/proto, then aliases) into a Service object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_service_format {
+    ( $entries: ident, $debug: ident ) => {{
+        let name = match $entries.next() {
+            Some(s) => s.to_string(),
+            _ => {
+                debug_print!(format!("Unable to parse name for services"), $debug);
+                return Response::TryAgain;
+            }
+        };
+        let (port, proto) = match $entries.next().and_then(|s| s.split_once('/')) {
+            Some((port, proto)) => match port.parse() {
+                Ok(port) => (port, proto.to_string()),
+                _ => {
+                    debug_print!(format!("Unable to parse port for services"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            _ => {
+                debug_print!(format!("Unable to parse port/proto for services"), $debug);
+                return Response::TryAgain;
+            }
+        };
+        Service {
+            name,
+            aliases: $entries.map(|x| x.to_string()).collect(),
+            port,
+            proto,
+        }
+    }};
+}
+
+/** Parse a JSON services object into one Service object per protocol
+- The entry can be a single object, or a list of objects if the service is defined for more than one protocol
+- If invalid, return the appropriate NSS code
+- If aliases are missing, they will be replaced with blanks */
+macro_rules! parse_service_json {
+    ( $service_entry: ident, $record: ident, $services: ident, $debug: ident ) => {
+        let mut $services: Vec<Service> = Vec::new();
+        let service_objects = match &*$record {
+            serde_json::Value::Array(x) => x.iter().collect(),
+            x => vec![x],
+        };
+        for service_object in service_objects {
+            parse_json_strings!(service_object.get("aliases"), aliases, "aliases for services JSON", $debug);
+            let port = match service_object["port"].as_u64() {
+                Some(x) if x <= u16::MAX as u64 => x as u16,
+                _ => {
+                    debug_print!(format!("Unable to parse port for services JSON"), $debug);
+                    return Response::TryAgain;
+                }
+            };
+            let proto = match service_object["proto"].as_str() {
+                Some(x) => x.to_string(),
+                _ => {
+                    debug_print!(format!("Unable to parse proto for services JSON"), $debug);
+                    return Response::TryAgain;
+                }
+            };
+            $services.push(Service {name: $service_entry.to_string(), aliases, port, proto});
+        }
+    };
+}
+
+/** Parse a line in /etc/protocols format (name, number, then aliases) into a Protocol object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_protocol_format {
+    ( $entries: ident, $debug: ident ) => {
+        Protocol {
+            name: match $entries.next() {
+                Some(s) => s.to_string(),
+                _ => {
+                    debug_print!(format!("Unable to parse name for protocols"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            number: match $entries.next().and_then(|s| s.parse().ok()) {
+                Some(s) => s,
+                _ => {
+                    debug_print!(format!("Unable to parse number for protocols"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            aliases: $entries.map(|x| x.to_string()).collect(),
+        }
+    };
+}
+
+/** Parse a JSON protocols object
+- If invalid, return the appropriate NSS code
+- If aliases are missing, they will be replaced with blanks */
+macro_rules! parse_protocol_json {
+    ( $protocol_entry: ident, $record: ident, $protocols: ident, $debug: ident ) => {
+        parse_json_strings!($record.get("aliases"), aliases, "aliases for protocols JSON", $debug);
+        let number = match $record["number"].as_i64().and_then(|x| i32::try_from(x).ok()) {
+            Some(x) => x,
+            _ => {
+                debug_print!(format!("Unable to parse number for protocols JSON"), $debug);
+                return Response::TryAgain;
+            }
+        };
+        let $protocols = vec![Protocol {name: $protocol_entry.to_string(), aliases, number}];
+    };
+}
+
+/** Parse a line in /etc/rpc format (name, number, then aliases) into an Rpc object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_rpc_format {
+    ( $entries: ident, $debug: ident ) => {
+        Rpc {
+            name: match $entries.next() {
+                Some(s) => s.to_string(),
+                _ => {
+                    debug_print!(format!("Unable to parse name for rpc"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            number: match $entries.next().and_then(|s| s.parse().ok()) {
+                Some(s) => s,
+                _ => {
+                    debug_print!(format!("Unable to parse number for rpc"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            aliases: $entries.map(|x| x.to_string()).collect(),
+        }
+    };
+}
+
+/** Parse a JSON rpc object
+- If invalid, return the appropriate NSS code
+- If aliases are missing, they will be replaced with blanks */
+macro_rules! parse_rpc_json {
+    ( $rpc_entry: ident, $record: ident, $rpcs: ident, $debug: ident ) => {
+        parse_json_strings!($record.get("aliases"), aliases, "aliases for rpc JSON", $debug);
+        let number = match $record["number"].as_i64().and_then(|x| i32::try_from(x).ok()) {
+            Some(x) => x,
+            _ => {
+                debug_print!(format!("Unable to parse number for rpc JSON"), $debug);
+                return Response::TryAgain;
+            }
+        };
+        let $rpcs = vec![Rpc {name: $rpc_entry.to_string(), aliases, number}];
+    };
+}
+
+/** Parse a line in /etc/networks format (name, number, then aliases) into a Network object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_network_format {
+    ( $entries: ident, $debug: ident ) => {
+        Network {
+            name: match $entries.next() {
+                Some(s) => s.to_string(),
+                _ => {
+                    debug_print!(format!("Unable to parse name for networks"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            net: match $entries.next().and_then(networks::parse_network) {
+                Some(s) => s,
+                _ => {
+                    debug_print!(format!("Unable to parse network for networks"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            aliases: $entries.map(|x| x.to_string()).collect(),
+        }
+    };
+}
+
+/** Parse a JSON networks object
+- If invalid, return the appropriate NSS code
+- If aliases are missing, they will be replaced with blanks */
+macro_rules! parse_network_json {
+    ( $network_entry: ident, $record: ident, $networks: ident, $debug: ident ) => {
+        parse_json_strings!($record.get("aliases"), aliases, "aliases for networks JSON", $debug);
+        let net = match $record["network"].as_str().and_then(networks::parse_network) {
+            Some(x) => x,
+            _ => {
+                debug_print!(format!("Unable to parse network for networks JSON"), $debug);
+                return Response::TryAgain;
+            }
+        };
+        let $networks = vec![Network {name: $network_entry.to_string(), aliases, net}];
+    };
+}
+
+/** Parse a line in /etc/ethers format (MAC address, then name) into an Ether object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_ether_format {
+    ( $entries: ident, $debug: ident ) => {
+        Ether {
+            mac: match $entries.next().and_then(ethers::parse_mac) {
+                Some(s) => s,
+                _ => {
+                    debug_print!(format!("Unable to parse MAC address for ethers"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            name: match $entries.next() {
+                Some(s) => s.to_string(),
+                _ => {
+                    debug_print!(format!("Unable to parse name for ethers"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+        }
+    };
+}
+
+/** Parse a JSON ethers object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_ether_json {
+    ( $ether_entry: ident, $record: ident, $ethers: ident, $debug: ident ) => {
+        let mac = match $record["mac"].as_str().and_then(ethers::parse_mac) {
+            Some(x) => x,
+            _ => {
+                debug_print!(format!("Unable to parse MAC address for ethers JSON"), $debug);
+                return Response::TryAgain;
+            }
+        };
+        let $ethers = vec![Ether {name: $ether_entry.to_string(), mac}];
+    };
+}
+
+/** Parse a line in /etc/aliases format (name, a colon, then members separated by commas) into an Alias object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_alias_format {
+    ( $line: ident, $debug: ident ) => {{
+        let (name, members) = match $line.split_once(':') {
+            Some((name, members)) if !name.trim().is_empty() => (name.trim(), members),
+            _ => {
+                debug_print!(format!("Unable to parse name for aliases: {}", $line), $debug);
+                return Response::TryAgain;
+            }
+        };
+        Alias {
+            name: name.to_string(),
+            members: members.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).map(|x| x.to_string()).collect(),
+            local: true,
+        }
+    }};
+}
+
+/** Parse a JSON aliases object
+- If invalid, return the appropriate NSS code
+- If members are missing, they will be replaced with blanks, and if local is missing it will be true */
+macro_rules! parse_alias_json {
+    ( $alias_entry: ident, $record: ident, $aliases: ident, $debug: ident ) => {
+        parse_json_strings!($record.get("members"), members, "members for aliases JSON", $debug);
+        let local = match $record.get("local") {
+            None => true,
+            Some(x) => match x.as_bool() {
+                Some(x) => x,
+                _ => {
+                    debug_print!(format!("Unable to parse local for aliases JSON"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+        };
+        let $aliases = vec![Alias {name: $alias_entry.to_string(), members, local}];
+    };
+}
+
+/** Parse the output of an aliases command, in either JSON or /etc/aliases format
+- Lines starting with whitespace continue the members of the previous line, as in /etc/aliases
+- Comments starting with `#` and blank lines are ignored
+- With a hidden ident, the positions of entries from JSON records with `"hidden": true` are collected in a HashSet
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_aliases {
+    ( $output: ident, $alias_vec: ident, $debug: ident ) => {
+        parse_aliases!($output, $alias_vec, _hidden, $debug);
+    };
+    ( $output: ident, $alias_vec: ident, $hidden: ident, $debug: ident ) => {
+        validate_response!($output, option, $debug);
+        let mut $alias_vec = Vec::new();
+        let mut $hidden: HashSet<usize> = HashSet::new();
+        match option {
+            Some(deser) => {
+                for record in deser.records() {
+                    let (json_entry, record) = valid_record!(record, $debug);
+                    parse_alias_json!(json_entry, record, parsed, $debug);
+                    if is_hidden(&record) {
+                        $hidden.extend($alias_vec.len()..$alias_vec.len() + parsed.len());
+                    }
+                    $alias_vec.extend(parsed);
+                }
+            }
+            _ => {
+                let mut lines: Vec<String> = Vec::new();
+                for line in $output.trim().lines() {
+                    if line.trim_start().starts_with('#') || line.trim().is_empty() {
+                        continue;
+                    }
+                    match lines.last_mut() {
+                        Some(last) if line.starts_with(char::is_whitespace) => {
+                            last.push(',');
+                            last.push_str(line);
+                        }
+                        _ => lines.push(line.to_string()),
+                    }
+                }
+                for line in lines {
+                    $alias_vec.push(parse_alias_format!(line, $debug));
+                }
+            }
+        };
+    };
+}
+
+/** Parse a line in /etc/subuid or /etc/subgid format (owner:start:count) into a SubidRange object
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_subid_format {
+    ( $entries: ident, $debug: ident ) => {{
+        let line = $entries.next().unwrap_or("");
+        let fields: Vec<&str> = line.split(':').collect();
+        match fields[..] {
+            [owner, start, count] if !owner.is_empty() => SubidRange {
+                owner: owner.to_string(),
+                start: match start.parse() {
+                    Ok(x) => x,
+                    _ => {
+                        debug_print!(format!("Unable to parse start for subid: {}", line), $debug);
+                        return Response::TryAgain;
+                    }
+                },
+                count: match count.parse() {
+                    Ok(x) => x,
+                    _ => {
+                        debug_print!(format!("Unable to parse count for subid: {}", line), $debug);
+                        return Response::TryAgain;
+                    }
+                },
+            },
+            _ => {
+                debug_print!(format!("Returned subid data: {} does not match expected unix form", line), $debug);
+                return Response::TryAgain;
+            }
+        }
+    }};
+}
+
+/** Parse a JSON subid object, which is a list of ranges with a start and count
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_subid_json {
+    ( $subid_entry: ident, $record: ident, $ranges: ident, $debug: ident ) => {
+        let mut $ranges = Vec::new();
+        for range in match $record.as_array() {
+            Some(x) => x,
+            _ => {
+                debug_print!(format!("Unable to parse ranges for subid JSON"), $debug);
+                return Response::TryAgain;
+            }
+        } {
+            match (range["start"].as_u64(), range["count"].as_u64()) {
+                (Some(start), Some(count)) => $ranges.push(SubidRange {owner: $subid_entry.to_string(), start, count}),
+                _ => {
+                    debug_print!(format!("Unable to parse start and count for subid JSON"), $debug);
+                    return Response::TryAgain;
+                }
+            }
+        }
+    };
+}
+
+/** Parse the output of a subid command, in either JSON or /etc/subuid format, and check that the ranges are valid
+- If invalid or if any ranges overlap, return the appropriate NSS code */
+macro_rules! parse_subid_ranges {
+    ( $output: ident, $ranges: ident, $debug: ident ) => {
+        parse_whitespace_entries!($output, $ranges, parse_subid_json, parse_subid_format, $debug);
+        if let Err(e) = subid::validate_ranges(&$ranges) {
+            debug_print!(format!("Returned subid data is invalid: {}", e), $debug);
+            return Response::TryAgain;
+        }
+    };
+}
+
+/** Parse a line in /etc/netgroup format (name, then triples and nested netgroups) into a name and members
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_netgroup_format {
+    ( $line: ident, $debug: ident ) => {{
+        let mut parts = $line.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or("").to_string();
+        match netgroup::parse_members(parts.next().unwrap_or("")) {
+            Some(members) => (name, members),
+            _ => {
+                debug_print!(format!("Unable to parse members of netgroup: {}", name), $debug);
+                return Response::TryAgain;
+            }
+        }
+    }};
+}
+
+/** Parse a JSON netgroup object, with optional lists of triples and nested netgroups
+- Each triple is an object with optional host, user, and domain strings
+- If invalid, return the appropriate NSS code */
+macro_rules! parse_netgroup_json {
+    ( $netgroup_entry: ident, $record: ident, $members: ident, $debug: ident ) => {
+        let mut $members = Vec::new();
+        if let Some(triples) = $record.get("triples") {
+            for triple in match triples.as_array() {
+                Some(x) => x,
+                _ => {
+                    debug_print!(format!("Unable to parse triples for netgroup JSON"), $debug);
+                    return Response::TryAgain;
+                }
+            } {
+                let mut fields = Vec::new();
+                for key in ["host", "user", "domain"] {
+                    fields.push(match triple.get(key) {
+                        None => None,
+                        Some(x) => match x.as_str() {
+                            Some(x) if !x.is_empty() => Some(x.to_string()),
+                            Some(_) => None,
+                            _ => {
+                                debug_print!(format!("Unable to parse {} in triple for netgroup JSON", key), $debug);
+                                return Response::TryAgain;
+                            }
+                        },
+                    });
+                }
+                let mut fields = fields.into_iter();
+                $members.push(NetgroupEntry::Triple {
+                    host: fields.next().flatten(),
+                    user: fields.next().flatten(),
+                    domain: fields.next().flatten(),
+                });
+            }
+        }
+        parse_json_strings!($record.get("netgroups"), netgroups, "netgroups for netgroup JSON", $debug);
+        $members.extend(netgroups.into_iter().map(NetgroupEntry::Group));
+    };
+}
+
+/** Build the command&args with the given workdir and env vars
+- If the code tuple has non-empty strings, instances of the first will be replaced by the second in args and env vars */
+macro_rules! build_command {
+    ($command: ident, $env_vars: ident, $dir: ident, $code: ident, $runnable_command: ident) => {
+        // Create the runnable command using the first item in the args (i.e. the base command to run)
+        let mut $runnable_command = Command::new(&$command[0]);
+        // Add arguments to the command by iterating through and replacing any of the special codes
+        for (i, arg) in $command.iter().enumerate() {
+            if i > 0 {
+                $runnable_command.arg(str::replace(arg, &$code.0, &$code.1));
+            }
+        }
+        // Add environment variables to the command by iterating through and replacing any of the special codes
+        for env_var in $env_vars.keys() {
+            $runnable_command.env(str::replace(env_var, &$code.0, &$code.1), str::replace(&$env_vars[env_var], &$code.0, &$code.1));
+        }
+        // Set working directory for running command, if present
+        if $dir.len() > 0 {
+            $runnable_command.current_dir($dir);
+        }
+    };
+}
+
+/** Run the command&args with the given workdir and env vars.
+- If the code tuple has non-empty strings, instances of the first will be replaced by the second in args and env vars
+- If the command has a runtime error or runs longer than the timeout, return NSS code TryAgain
+- If the command succeeds, return the trimmed output */
+macro_rules! run_command_capture_output {
+    ($command: ident, $env_vars: ident, $dir: ident, $timeout: ident, $code: ident, $output: ident, $debug: ident) => {
+        build_command!($command, $env_vars, $dir, $code, runnable_command);
+        // Run the command and capture the output, returning NSS code TryAgain if something goes wrong
+        let stdout = match $timeout {
+            Some(timeout) => output_with_timeout(&mut runnable_command, timeout),
+            None => runnable_command.stdout(Stdio::piped()).output().map(|x| x.stdout),
+        };
+        let $output = match String::from_utf8(
+            match stdout {
+                Ok(x) => x,
+                Err(e) => {
+                    debug_print!(format!("Runtime error for command: {}", e), $debug);
+                    return Response::TryAgain
+                }
+            }
+        ) {
+            Ok(mut x) => {
+                x = x.trim().to_string();
+                x
+            }
+            _ => {
+                debug_print!(format!("Unable to capture output from command as string"), $debug);
+                return Response::TryAgain
+            }
+        };
+    };
+}
+
+/** Start the command&args like run_command_capture_output, but read its output one line at a time (see OutputLines)
+- If the command cannot be started, return NSS code TryAgain */
+macro_rules! spawn_command_lines {
+    ($command: ident, $env_vars: ident, $dir: ident, $timeout: ident, $code: ident, $lines: ident, $debug: ident) => {
+        build_command!($command, $env_vars, $dir, $code, runnable_command);
+        let $lines = match OutputLines::spawn(&mut runnable_command, $timeout) {
+            Ok(lines) => lines,
+            Err(e) => {
+                debug_print!(format!("Runtime error for command: {}", e), $debug);
+                return Response::TryAgain;
+            }
+        };
+    };
+}
+
+/** Run get_all_entries for a database, parsing JSON Lines one line at a time as the output is read
+- If the output is JSON Lines, return its entries (see listed_entries)
+- If the output is in another format, it is read whole into `$output`, to be parsed as usual
+- If the on-disk cache is enabled, the command is not run here, since its whole output is needed to cache it
+- If invalid, return the appropriate NSS code */
+macro_rules! stream_all_entries {
+    ($config_deser: ident, $database: ident, $function: ident, $code: ident, $parse_json: ident, $listing: ident, $output: ident, $debug: ident) => {
+        let mut $output: Option<String> = None;
+        if !cache::uses_disk_cache(&$config_deser, &$database, &$function, $debug) {
+            parse_config!($config_deser, $database, $function, command, env_vars, dir, timeout, $debug);
+            parse_options!($config_deser, $database, $function, options, $debug);
+            spawn_command_lines!(command, env_vars, dir, timeout, $code, lines, $debug);
+            let streamed = stream_json_lines(lines, $debug, |json_entry, record| {
+                $parse_json!(json_entry, record, parsed, options.validation, &options.defaults, $debug);
+                Response::Success(parsed)
+            });
+            match streamed {
+                Response::Success(StreamedOutput::Records((entries, hidden))) => {
+                    return listed_entries(&$database, entries, hidden, $listing, $debug);
+                }
+                Response::Success(StreamedOutput::Whole(output)) => $output = Some(output),
+                response => return failure(response),
+            }
+        }
+    };
+}
+
+/** Answer a lookup from the output of get_all_entries if configured to do so
+- If the lookup should be derived, return the first entry matching the closure, or NSS code NotFound
+- If get_all_entries fails, return its NSS code
+- If derive_from_all is invalid, return NSS code Unavail */
+macro_rules! derive_from_all {
+    ($config_deser: ident, $target_db: ident, $target_function: ident, $matches: expr, $debug: ident) => {
+        match should_derive(&$config_deser, &$target_db, &$target_function, $debug) {
+            Ok(true) => {
+                debug_print!(
+                    format!("Deriving {} {} from get_all_entries", $target_db, $target_function),
+                    $debug
+                );
+                return match Self::all_entries(false) {
+                    Response::Success(entries) => match entries.into_iter().find($matches) {
+                        Some(entry) => Response::Success(entry),
+                        None => Response::NotFound,
+                    },
+                    response => failure(response),
+                };
+            }
+            Ok(false) => {}
+            Err(()) => return Response::Unavail,
+        }
+    };
+    // Derived lookups are never used to build entries from get_all_names, so they are given as not hidden
+    ($config_deser: ident, $target_db: ident, $target_function: ident, $matches: expr, hidden, $debug: ident) => {
+        match should_derive(&$config_deser, &$target_db, &$target_function, $debug) {
+            Ok(true) => {
+                debug_print!(
+                    format!("Deriving {} {} from get_all_entries", $target_db, $target_function),
+                    $debug
+                );
+                return match Self::all_entries(false) {
+                    Response::Success(entries) => match entries.into_iter().find($matches) {
+                        Some(entry) => Response::Success((entry, false)),
+                        None => Response::NotFound,
+                    },
+                    response => failure(response),
+                };
+            }
+            Ok(false) => {}
+            Err(()) => return Response::Unavail,
+        }
+    };
+}
+
+/** Answer a query from the index file of a database, if the `index` backend is configured
+- With a key, return the matching entry or NSS code NotFound, and with `hidden`, whether it is hidden as well
+- Without a key, return all entries, or when listing them for enumeration, leave out hidden entries and apply the
+  `enumerate` setting
+- If the index file cannot be read, return NSS code Unavail */
+macro_rules! read_index {
+    ($config_deser: ident, $target_db: ident, $target_function: ident, $parse_format: ident, $listing: ident, $debug: ident) => {
+        match index::use_index(&$config_deser, &$target_db, &$target_function, $debug) {
+            Ok(true) => {
+                // Only the entries which are listed are read
+                let max_entries = match $listing {
+                    true => match enumerate_limit(&$config_deser, &$target_db, $debug) {
+                        Ok(max_entries) => max_entries.unwrap_or(usize::MAX),
+                        Err(()) => return Response::Unavail,
+                    },
+                    false => usize::MAX,
+                };
+                return match index::read_all(&$target_db, $listing, max_entries, $debug) {
+                    Response::Success(records) => {
+                        let mut entry_vec = Vec::new();
+                        for (fields, _) in records {
+                            let mut entries = fields.iter().map(|x| x.as_str());
+                            entry_vec.push($parse_format!(entries, Validation::Default, &serde_json::Map::new(), $debug));
+                        }
+                        Response::Success(entry_vec)
+                    }
+                    response => failure(response),
+                };
+            }
+            Ok(false) => {}
+            Err(()) => return Response::Unavail,
+        }
+    };
+    ($config_deser: ident, $target_db: ident, $target_function: ident, $key: expr, $parse_format: ident, hidden, $debug: ident) => {
+        match index::use_index(&$config_deser, &$target_db, &$target_function, $debug) {
+            Ok(true) => {
+                return match index::lookup(&$target_db, $key, $debug) {
+                    Response::Success((fields, hidden)) => {
+                        let mut entries = fields.iter().map(|x| x.as_str());
+                        let entry = $parse_format!(entries, Validation::Default, &serde_json::Map::new(), $debug);
+                        Response::Success((entry, hidden))
+                    }
+                    response => failure(response),
+                };
+            }
+            Ok(false) => {}
+            Err(()) => return Response::Unavail,
+        }
+    };
+    ($config_deser: ident, $target_db: ident, $target_function: ident, $key: expr, $parse_format: ident, $debug: ident) => {
+        match index::use_index(&$config_deser, &$target_db, &$target_function, $debug) {
+            Ok(true) => {
+                return match index::lookup(&$target_db, $key, $debug) {
+                    Response::Success((fields, _)) => {
+                        let mut entries = fields.iter().map(|x| x.as_str());
+                        Response::Success($parse_format!(entries, Validation::Default, &serde_json::Map::new(), $debug))
+                    }
+                    response => failure(response),
+                };
+            }
+            Ok(false) => {}
+            Err(()) => return Response::Unavail,
+        }
+    };
+}
+
+/** Run the command for one page of a paged get_all_entries, with the `<$offset>`, `<$limit>`, and `<$cursor>` codes
+- JSON output has entries in the usual format under `entries`, and the cursor of the next page (if any) under `next`
+- Without a cursor, there is another page only if this one is full (it has `limit` entries)
+- If invalid, return the appropriate NSS code */
+macro_rules! run_page {
+    (
+        $config_deser: ident,
+        $database: ident,
+        $position: ident,
+        $limit: ident,
+        $parse_json: ident,
+        $parse_format: ident,
+        $colons: expr,
+        $fields: expr,
+        $listing: ident,
+        $debug: ident
+    ) => {{
+        let function = "get_all_entries".to_string();
+        let code = ("<$offset>".to_string(), $position.offset.to_string());
+        let limit_code = ("<$limit>".to_string(), $limit.to_string());
+        let cursor_code = ("<$cursor>".to_string(), $position.cursor.clone());
+        // Each page is cached separately
+        let query = format!("{}/{}", $position.offset, $position.cursor);
+        let run = || {
+            parse_config!($config_deser, $database, function, command, env_vars, dir, timeout, $debug);
+            let (command, env_vars) = replace_code(command, env_vars, &limit_code);
+            let (command, env_vars) = replace_code(command, env_vars, &cursor_code);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, $debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&$config_deser, &$database, &function, &query, $debug, run, |output| {
+            parse_options!($config_deser, $database, function, options, $debug);
+            let mut entry_vec = Vec::new();
+            let mut hidden: HashSet<usize> = HashSet::new();
+            let mut cursor = None;
+            match serde_json::from_str::<serde_json::Value>(&output).ok() {
+                Some(page) if page.is_object() && !is_json_record(&page) => {
+                    // A page can also have a status, alongside its entries and the next cursor
+                    match envelope_status(&page, $debug) {
+                        Some(Response::Success(())) | None => {}
+                        Some(response) => return failure(response),
+                    }
+                    let deser = match page.get("entries") {
+                        Some(entries) if entries.is_object() || is_json_records(entries) => entries,
+                        _ => {
+                            debug_print!(format!("Returned {} page has no entries object or array", $database), $debug);
+                            return Response::TryAgain;
+                        }
+                    };
+                    for (json_entry, record) in json_records(&deser) {
+                        if is_hidden(&record) {
+                            hidden.insert(entry_vec.len());
+                        }
+                        $parse_json!(json_entry, record, parsed, options.validation, &options.defaults, $debug);
+                        entry_vec.push(parsed);
+                    }
+                    cursor = page["next"].as_str().filter(|x| !x.is_empty()).map(|x| x.to_string());
+                }
+                // JSON Lines pages have no cursor, so they end in the same way as colon-separated pages
+                _ if is_json_lines(&output) => {
+                    for record in JsonOutput::Lines(&output).records() {
+                        let (json_entry, record) = valid_record!(record, $debug);
+                        if is_hidden(&record) {
+                            hidden.insert(entry_vec.len());
+                        }
+                        $parse_json!(json_entry, record, parsed, options.validation, &options.defaults, $debug);
+                        entry_vec.push(parsed);
+                    }
+                }
+                _ => {
+                    for line in colon_lines(&output) {
+                        let fields = split_colons(line, $fields, options.escaped_colons);
+                        if fields.len() < $colons + 1 {
+                            debug_print!(
+                                format!("Returned {} data: {} does not match expected unix form", $database, line),
+                                $debug
+                            );
+                            return Response::TryAgain;
+                        }
+                        let mut entries = fields.iter().map(|x| x.as_str());
+                        entry_vec.push($parse_format!(entries, options.validation, &options.defaults, $debug));
+                    }
+                }
+            };
+            if cursor.is_some() && cursor.as_deref() == Some($position.cursor.as_str()) {
+                debug_print!(format!("Returned {} page has the same cursor as the last page", $database), $debug);
+                return Response::TryAgain;
+            }
+            // The offset counts every entry returned by the command, including hidden entries
+            let offset = $position.offset + entry_vec.len();
+            let full = entry_vec.len() >= $limit;
+            let more = !entry_vec.is_empty();
+            if $listing {
+                entry_vec = without_hidden(entry_vec, &hidden);
+                hidden.clear();
+            }
+            let listed = $position.listed + entry_vec.len();
+            let next = match cursor {
+                Some(cursor) if more => Some(Position {offset, cursor, listed}),
+                None if more && full => Some(Position {offset, cursor: "".to_string(), listed}),
+                _ => None,
+            };
+            Response::Success(Page {entries: entry_vec, hidden, next})
+        })
+    }};
+}
+
+/** Run the get_changes_since command for a database and parse the output
+- The output must be a JSON object with the new "token", and optionally "upserts" (entries in the same JSON format as
+  get_all_entries), "deletions" (a list of names), or "resync": true if the given token was rejected
+- If the output is not formatted as expected, return NSS code TryAgain */
+macro_rules! run_changes_since {
+    ($config_deser: ident, $target_db: ident, $token: ident, $parse_json: ident, $debug: ident) => {{
+        let function = "get_changes_since".to_string();
+        let code = ("<$token>".to_string(), $token);
+        parse_config!($config_deser, $target_db, function, command, env_vars, dir, timeout, $debug);
+        parse_options!($config_deser, $target_db, function, options, $debug);
+        run_command_capture_output!(command, env_vars, dir, timeout, code, output, $debug);
+        let deser: serde_json::Value = match serde_json::from_str(&output) {
+            Ok(deser) => deser,
+            Err(e) => {
+                debug_print!(format!("get_changes_since response: {} is invalid: {}", output, e), $debug);
+                return Response::TryAgain;
+            }
+        };
+        let resync = match deser.get("resync") {
+            Some(resync) => match resync.as_bool() {
+                Some(resync) => resync,
+                _ => {
+                    debug_print!(format!("Unable to parse resync for get_changes_since JSON"), $debug);
+                    return Response::TryAgain;
+                }
+            },
+            _ => false,
+        };
+        let token = match deser.get("token").and_then(|x| x.as_str()) {
+            Some(token) => token.to_string(),
+            _ if resync => "".to_string(),
+            _ => {
+                debug_print!(format!("Unable to parse token for get_changes_since JSON"), $debug);
+                return Response::TryAgain;
+            }
+        };
+        let mut upserts = Vec::new();
+        let mut hidden = HashSet::new();
+        if let Some(upserts_deser) = deser.get("upserts") {
+            if !upserts_deser.is_object() && !is_json_records(upserts_deser) {
+                debug_print!(format!("Unable to parse upserts for get_changes_since JSON"), $debug);
+                return Response::TryAgain;
+            }
+            for (upsert_entry, record) in json_records(upserts_deser) {
+                if is_hidden(record) {
+                    hidden.insert(upserts.len());
+                }
+                $parse_json!(upsert_entry, record, upsert, options.validation, &options.defaults, $debug);
+                upserts.push(upsert);
+            }
+        }
+        let mut deletions = Vec::new();
+        if let Some(deletions_deser) = deser.get("deletions") {
+            for deletion in match deletions_deser.as_array() {
+                Some(x) => x,
+                _ => {
+                    debug_print!(format!("Unable to parse deletions for get_changes_since JSON"), $debug);
+                    return Response::TryAgain;
+                }
+            } {
+                match deletion.as_str() {
+                    Some(x) => deletions.push(x.to_string()),
+                    _ => {
+                        debug_print!(format!("Unable to parse deletion for get_changes_since JSON"), $debug);
+                        return Response::TryAgain;
+                    }
+                }
+            }
+        }
+        Response::Success(index::Changes {token, upserts, hidden, deletions, resync})
+    }};
+}
+
+mod cache;
+mod index;
+
+// The NSS functions that libnss does not provide are exported with these macros
+#[macro_use]
+extern crate nss_shim_ffi;
+
+pub use cache::flush;
+
+/////////////////
+//  Functions  //
+/////////////////
+
+/** Find a setting in config.json, checking the function, database, and global levels in that order
+- If the setting is not defined at any of those levels, return None */
+fn find_setting<'a>(
+    config: &'a serde_json::Value,
+    database: &str,
+    function: &str,
+    key: &str,
+) -> Option<&'a serde_json::Value> {
+    [
+        &config["databases"][database]["functions"][function],
+        &config["databases"][database],
+        config,
+    ]
+    .into_iter()
+    .find_map(|level| level.get(key))
+}
+
+/** Replace a code in the args and env vars of a command before it is run
+- This allows a function to have more than one code, since run_command_capture_output only replaces one */
+fn replace_code(
+    command: Vec<String>,
+    env_vars: HashMap<String, String>,
+    code: &(String, String),
+) -> (Vec<String>, HashMap<String, String>) {
+    let command = command.iter().map(|x| str::replace(x, &code.0, &code.1)).collect();
+    let env_vars = env_vars
+        .iter()
+        .map(|(k, v)| (str::replace(k, &code.0, &code.1), str::replace(v, &code.0, &code.1)))
+        .collect();
+    (command, env_vars)
+}
+
+/** Determine if a lookup should be answered from the output of get_all_entries instead of its own command
+- If `derive_from_all` is true, always derive lookups; if it is false, never derive lookups
+- If `derive_from_all` is not set, derive lookups when the function is not configured but get_all_entries is
+- If `derive_from_all` is not a boolean, return Err */
+fn should_derive(config: &serde_json::Value, database: &str, function: &str, debug: bool) -> Result<bool, ()> {
+    match find_setting(config, database, function, "derive_from_all") {
+        Some(derive) => match derive.as_bool() {
+            Some(derive) => Ok(derive),
+            None => {
+                debug_print!("derive_from_all in config.json must be a boolean", debug);
+                Err(())
+            }
+        },
+        None => {
+            let functions = &config["databases"][database]["functions"];
+            let listed = functions.get("get_all_entries").is_some() || functions.get("get_all_names").is_some();
+            Ok(functions.get(function).is_none() && listed)
+        }
+    }
+}
+
+/** Assemble a GECOS string from a JSON object of its parts, in the order full name, room, work phone, home phone, other
+- Each part is an optional string named `full_name`, `room`, `work_phone`, `home_phone`, or `other`
+- Commas and colons are removed from each part, and empty parts at the end are left out
+- If a part is not a string, return Err with its name */
+fn gecos_from_parts(parts: &serde_json::Map<String, serde_json::Value>) -> Result<String, String> {
+    let mut gecos = Vec::new();
+    for key in ["full_name", "room", "work_phone", "home_phone", "other"] {
+        gecos.push(match parts.get(key) {
+            None | Some(serde_json::Value::Null) => "".to_string(),
+            Some(serde_json::Value::String(x)) => x.replace([',', ':'], ""),
+            Some(_) => return Err(key.to_string()),
+        });
+    }
+    while gecos.last().is_some_and(|x| x.is_empty()) {
+        gecos.pop();
+    }
+    Ok(gecos.join(","))
+}
+
+/** Determine if colons in fields are escaped in colon-separated command output, from the `escaped_colons` setting
+- If `escaped_colons` is not set, they are not
+- If `escaped_colons` is not a boolean, return Err */
+fn escaped_colons(config: &serde_json::Value, database: &str, function: &str, debug: bool) -> Result<bool, ()> {
+    match find_setting(config, database, function, "escaped_colons") {
+        None => Ok(false),
+        Some(escaped) => match escaped.as_bool() {
+            Some(escaped) => Ok(escaped),
+            None => {
+                debug_print!("escaped_colons in config.json must be a boolean", debug);
+                Err(())
+            }
+        },
+    }
+}
+
+/** List the lines of colon-separated command output, leaving out comments starting with `#` and blank lines */
+fn colon_lines(output: &str) -> impl Iterator<Item = &str> {
+    output.lines().map(|x| x.trim()).filter(|x| !x.is_empty() && !x.starts_with('#'))
+}
+
+/** Split a line of colon-separated command output into at most `count` fields
+- With escaped colons, a colon in a field is written as `\:` or `\x3a` (any ASCII character can be written as `\xHH`)
+  and a backslash as `\\`, and each field is decoded
+- Without, the last field keeps any remaining colons */
+fn split_colons(line: &str, count: usize, escaped: bool) -> Vec<String> {
+    if !escaped {
+        return line.splitn(count, ':').map(|x| x.to_string()).collect();
+    }
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ':' if fields.len() + 1 < count => fields.push(std::mem::take(&mut field)),
+            '\\' => match chars.next() {
+                Some('x') => {
+                    let hex: String = chars.clone().take(2).collect();
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(byte) if hex.len() == 2 && hex.chars().all(|x| x.is_ascii_hexdigit()) && byte.is_ascii() => {
+                            field.push(byte as char);
+                            chars.nth(1);
+                        }
+                        _ => field.push_str("\\x"),
+                    }
+                }
+                Some(escaped) => field.push(escaped),
+                None => field.push('\\'),
+            },
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/** How strictly the output of a command is checked, from the `validation` setting of a database */
+#[derive(Clone, Copy, PartialEq)]
+enum Validation {
+    Default,
+    // Ids are range checked, unknown JSON fields are rejected, and passwd entries need a dir and shell
+    Strict,
+    // Numeric strings are accepted as numbers, and null as an unset field
+    Lenient,
+}
+
+/** Find the validation mode of a database, from the `validation` setting
+- If `validation` is not set, use the default mode
+- If `validation` is not "strict", "lenient", or "default", return Err */
+fn validation_mode(config: &serde_json::Value, database: &str, function: &str, debug: bool) -> Result<Validation, ()> {
+    match find_setting(config, database, function, "validation") {
+        None => Ok(Validation::Default),
+        Some(validation) => match validation.as_str() {
+            Some("default") => Ok(Validation::Default),
+            Some("strict") => Ok(Validation::Strict),
+            Some("lenient") => Ok(Validation::Lenient),
+            _ => {
+                debug_print!("validation in config.json must be \"strict\", \"lenient\", or \"default\"", debug);
+                Err(())
+            }
+        },
+    }
+}
+
+/** List the JSON fields of an entry of a database, and which of them are numbers */
+fn json_fields(database: &str) -> (&'static [&'static str], &'static [&'static str]) {
+    match database {
+        "group" => (&["name", "hidden", "passwd", "gid", "members"], &["gid"]),
+        "passwd" => (&["name", "hidden", "passwd", "uid", "gid", "gecos", "dir", "shell"], &["uid", "gid"]),
+        "shadow" => (
+            &[
+                "name",
+                "hidden",
+                "passwd",
+                "last_change",
+                "change_min_days",
+                "change_max_days",
+                "change_warn_days",
+                "change_inactive_days",
+                "expire_date",
+                "reserved",
+            ],
+            &[
+                "last_change",
+                "change_min_days",
+                "change_max_days",
+                "change_warn_days",
+                "change_inactive_days",
+                "expire_date",
+                "reserved",
+            ],
+        ),
+        "gshadow" => (&["name", "hidden", "passwd", "administrators", "members"], &[]),
+        _ => (&[], &[]),
+    }
+}
+
+/** Check a JSON entry of a database before it is parsed
+- In strict mode, if the entry has a field which is not known, return Err with its name
+- In lenient mode, numeric strings in number fields are converted to numbers, and null number fields are removed */
+fn checked_record<'a>(
+    record: &'a serde_json::Value,
+    database: &str,
+    validation: Validation,
+) -> Result<Cow<'a, serde_json::Value>, String> {
+    let (fields, numbers) = json_fields(database);
+    match (validation, record.as_object()) {
+        (Validation::Strict, Some(object)) => match object.keys().find(|x| !fields.contains(&x.as_str())) {
+            Some(field) => Err(field.to_string()),
+            None => Ok(Cow::Borrowed(record)),
+        },
+        (Validation::Lenient, Some(object)) => {
+            let mut object = object.clone();
+            for field in numbers {
+                match object.get(*field) {
+                    Some(serde_json::Value::Null) => {
+                        object.remove(*field);
+                    }
+                    Some(serde_json::Value::String(x)) => {
+                        if let Ok(number) = x.trim().parse::<serde_json::Number>() {
+                            object.insert(field.to_string(), serde_json::Value::Number(number));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Cow::Owned(serde_json::Value::Object(object)))
+        }
+        _ => Ok(Cow::Borrowed(record)),
+    }
+}
+
+/** Parse a uid or gid field of a JSON entry
+- In strict mode, ids which do not fit in 32 bits are rejected, rather than truncated */
+fn json_id(field: &serde_json::Value, validation: Validation) -> Option<u32> {
+    match validation {
+        Validation::Strict => field.as_u64().and_then(|x| u32::try_from(x).ok()),
+        _ => field.as_u64().map(|x| x as u32),
+    }
+}
+
+/** Parse a number field of colon-separated command output
+- In lenient mode, whitespace around the number is ignored */
+fn format_number<T: std::str::FromStr>(field: Option<&str>, validation: Validation) -> Option<T> {
+    match validation {
+        Validation::Lenient => field.and_then(|x| x.trim().parse().ok()),
+        _ => field.and_then(|x| x.parse().ok()),
+    }
+}
+
+/** Parse an optional number field of colon-separated command output, such as the dates of a shadow entry
+- If the field is missing or blank, or cannot be parsed in lenient mode, return `unset`
+- Otherwise, if the field cannot be parsed, return Err with the field */
+fn format_optional_number<T: std::str::FromStr>(field: Option<&str>, unset: T, validation: Validation) -> Result<T, String> {
+    match field {
+        None | Some("") => Ok(unset),
+        Some(x) => match format_number(Some(x), validation) {
+            Some(number) => Ok(number),
+            None if validation == Validation::Lenient => Ok(unset),
+            None => Err(x.to_string()),
+        },
+    }
+}
+
+/** Find a required field which is blank in a passwd entry
+- In strict mode, dir and shell are required */
+fn missing_passwd_field(passwd: &Passwd, validation: Validation) -> Option<&'static str> {
+    match validation {
+        Validation::Strict if passwd.dir.is_empty() => Some("dir"),
+        Validation::Strict if passwd.shell.is_empty() => Some("shell"),
+        _ => None,
+    }
+}
+
+/** Find the defaults of a database for fields which are missing or blank in its entries, from the `defaults` setting
+- Strings can be given for `passwd`, and for `gecos`, `dir`, and `shell` of passwd entries, and integers for the
+  number fields of shadow entries
+- If `defaults` is not set, return no defaults
+- If `defaults` is not an object of these fields, return Err */
+fn field_defaults(
+    config: &serde_json::Value,
+    database: &str,
+    function: &str,
+    debug: bool,
+) -> Result<serde_json::Map<String, serde_json::Value>, ()> {
+    let defaults = match find_setting(config, database, function, "defaults") {
+        None => return Ok(serde_json::Map::new()),
+        Some(defaults) => match defaults.as_object() {
+            Some(defaults) => defaults,
+            None => {
+                debug_print!("defaults in config.json must be an object", debug);
+                return Err(());
+            }
+        },
+    };
+    let strings: &[&str] = match database {
+        "passwd" => &["passwd", "gecos", "dir", "shell"],
+        _ => &["passwd"],
+    };
+    let (_, numbers) = json_fields(database);
+    for (field, default) in defaults {
+        let valid = match default {
+            serde_json::Value::String(_) => strings.contains(&field.as_str()),
+            serde_json::Value::Number(x) if field == "reserved" => x.as_u64().is_some(),
+            serde_json::Value::Number(x) => {
+                database == "shadow" && numbers.contains(&field.as_str()) && x.as_i64().is_some()
+            }
+            _ => false,
+        };
+        if !valid {
+            debug_print!(format!("defaults in config.json has an invalid {} for {}", field, database), debug);
+            return Err(());
+        }
+    }
+    Ok(defaults.clone())
+}
+
+/** Options for parsing the entries of a database in command output */
+struct ParseOptions {
+    validation: Validation,
+    defaults: serde_json::Map<String, serde_json::Value>,
+    escaped_colons: bool,
+}
+
+/** Find the options for parsing the entries of a database in command output, from its settings
+- If the `validation`, `defaults`, or `escaped_colons` setting is invalid, return Err */
+fn parse_options(config: &serde_json::Value, database: &str, function: &str, debug: bool) -> Result<ParseOptions, ()> {
+    Ok(ParseOptions {
+        validation: validation_mode(config, database, function, debug)?,
+        defaults: field_defaults(config, database, function, debug)?,
+        escaped_colons: escaped_colons(config, database, function, debug)?,
+    })
+}
+
+/** Fill in a blank string field of an entry from its default, with the codes of the entry (e.g. `<$name>`) replaced */
+fn default_string(
+    field: &mut String,
+    key: &str,
+    defaults: &serde_json::Map<String, serde_json::Value>,
+    codes: &[(&str, String)],
+) {
+    if let (true, Some(default)) = (field.is_empty(), defaults.get(key).and_then(|x| x.as_str())) {
+        *field = codes.iter().fold(default.to_string(), |x, (code, value)| x.replace(code, value));
+    }
+}
+
+/** Fill in an unset number field of an entry from its default */
+fn default_number<T: PartialEq + TryFrom<i64> + TryFrom<u64>>(
+    field: &mut T,
+    unset: T,
+    key: &str,
+    defaults: &serde_json::Map<String, serde_json::Value>,
+) {
+    if *field != unset {
+        return;
+    }
+    let default = match defaults.get(key) {
+        Some(serde_json::Value::Number(x)) => match (x.as_i64(), x.as_u64()) {
+            (Some(x), _) => T::try_from(x).ok(),
+            (_, Some(x)) => T::try_from(x).ok(),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(default) = default {
+        *field = default;
+    }
+}
+
+/** Fill in the missing or blank fields of a passwd entry from the `defaults` setting
+- `<$name>`, `<$uid>`, and `<$gid>` are replaced with those of the entry */
+fn passwd_defaults(passwd: &mut Passwd, defaults: &serde_json::Map<String, serde_json::Value>) {
+    if defaults.is_empty() {
+        return;
+    }
+    let codes = [
+        ("<$name>", passwd.name.clone()),
+        ("<$uid>", passwd.uid.to_string()),
+        ("<$gid>", passwd.gid.to_string()),
+    ];
+    default_string(&mut passwd.passwd, "passwd", defaults, &codes);
+    default_string(&mut passwd.gecos, "gecos", defaults, &codes);
+    default_string(&mut passwd.dir, "dir", defaults, &codes);
+    default_string(&mut passwd.shell, "shell", defaults, &codes);
+}
+
+/** Fill in the missing or blank fields of a group entry from the `defaults` setting
+- `<$name>` and `<$gid>` are replaced with those of the entry */
+fn group_defaults(group: &mut Group, defaults: &serde_json::Map<String, serde_json::Value>) {
+    if defaults.is_empty() {
+        return;
+    }
+    let codes = [("<$name>", group.name.clone()), ("<$gid>", group.gid.to_string())];
+    default_string(&mut group.passwd, "passwd", defaults, &codes);
+}
+
+/** Fill in the missing or blank fields of a shadow entry from the `defaults` setting
+- `<$name>` is replaced with the name of the entry */
+fn shadow_defaults(shadow: &mut Shadow, defaults: &serde_json::Map<String, serde_json::Value>) {
+    if defaults.is_empty() {
+        return;
+    }
+    default_string(&mut shadow.passwd, "passwd", defaults, &[("<$name>", shadow.name.clone())]);
+    default_number(&mut shadow.last_change, -1, "last_change", defaults);
+    default_number(&mut shadow.change_min_days, -1, "change_min_days", defaults);
+    default_number(&mut shadow.change_max_days, -1, "change_max_days", defaults);
+    default_number(&mut shadow.change_warn_days, -1, "change_warn_days", defaults);
+    default_number(&mut shadow.change_inactive_days, -1, "change_inactive_days", defaults);
+    default_number(&mut shadow.expire_date, -1, "expire_date", defaults);
+    default_number(&mut shadow.reserved, usize::MAX, "reserved", defaults);
+}
+
+/** Fill in the missing or blank fields of a gshadow entry from the `defaults` setting
+- `<$name>` is replaced with the name of the entry */
+fn gshadow_defaults(gshadow: &mut GShadow, defaults: &serde_json::Map<String, serde_json::Value>) {
+    if defaults.is_empty() {
+        return;
+    }
+    default_string(&mut gshadow.passwd, "passwd", defaults, &[("<$name>", gshadow.name.clone())]);
+}
+
+/** Find the number of entries per page for get_all_entries, if it is paged
+- If `page_size` is not set, get_all_entries prints every entry at once
+- If `page_size` is not a positive integer, return Err */
+fn page_size(config: &serde_json::Value, database: &str, debug: bool) -> Result<Option<usize>, ()> {
+    match find_setting(config, database, "get_all_entries", "page_size") {
+        None => Ok(None),
+        Some(size) => match size.as_u64() {
+            Some(size) if size > 0 => Ok(Some(size as usize)),
+            _ => {
+                debug_print!("page_size in config.json must be a positive integer", debug);
+                Err(())
+            }
+        },
+    }
+}
+
+/** Find the maximum number of entries to list when a database is enumerated (e.g. by `getent passwd`)
+- If `enumerate` is not set or is true, every entry is listed, so return None
+- If `enumerate` is false, no entries are listed, and with `"enumerate": {"max_entries": N}` at most N are listed
+- If `enumerate` is not one of those, return Err */
+fn enumerate_limit(config: &serde_json::Value, database: &str, debug: bool) -> Result<Option<usize>, ()> {
+    match find_setting(config, database, "get_all_entries", "enumerate") {
+        None | Some(serde_json::Value::Bool(true)) => Ok(None),
+        Some(serde_json::Value::Bool(false)) => Ok(Some(0)),
+        Some(enumerate) => match enumerate.get("max_entries").and_then(|x| x.as_u64()) {
+            Some(max_entries) => Ok(Some(max_entries as usize)),
+            None => {
+                debug_print!("enumerate in config.json must be a boolean or an object with max_entries", debug);
+                Err(())
+            }
+        },
+    }
+}
+
+/** Find the status of JSON command output with a status envelope, and log its message
+- An envelope is an object like `{"status": "unavail", "message": "ldap down", "entries": {...}}`
+- Objects keyed by name cannot have a string as an entry, so they are never mistaken for an envelope
+- The status can be `success`, `notfound`, `unavail`, or `tryagain`, and any other status is NSS code TryAgain
+- If there is no envelope, return None */
+fn envelope_status(deser: &serde_json::Value, debug: bool) -> Option<Response<()>> {
+    let status = deser.get("status")?.as_str()?;
+    match deser.get("message").and_then(|x| x.as_str()) {
+        Some(message) => debug_print!(format!("Command returned status {}: {}", status, message), debug),
+        None => debug_print!(format!("Command returned status {}", status), debug),
+    }
+    Some(match status {
+        "success" => Response::Success(()),
+        "notfound" => Response::NotFound,
+        "unavail" => Response::Unavail,
+        "tryagain" => Response::TryAgain,
+        _ => {
+            debug_print!(format!("Unknown status {} in JSON", status), debug);
+            Response::TryAgain
+        }
+    })
+}
+
+/** Unwrap the entries of JSON command output with a status envelope
+- If the status is success, return its entries, or NSS code NotFound if there are none
+- If the status is not success, return its NSS code
+- If there is no envelope, return the output unchanged */
+fn open_envelope(mut deser: serde_json::Value, debug: bool) -> Result<serde_json::Value, Response<()>> {
+    match envelope_status(&deser, debug) {
+        None => Ok(deser),
+        Some(Response::Success(())) => match deser.get_mut("entries") {
+            Some(entries) => Ok(entries.take()),
+            None => Err(Response::NotFound),
+        },
+        Some(response) => Err(response),
+    }
+}
+
+/** Determine if JSON is a single record, an object with a `"name"` string
+- Objects keyed by name cannot have a string as an entry, so they are never mistaken for a record */
+fn is_json_record(deser: &serde_json::Value) -> bool {
+    deser.get("name").is_some_and(|x| x.is_string())
+}
+
+/** Determine if JSON command output is a list of records, each an object with a `"name"` string */
+fn is_json_records(deser: &serde_json::Value) -> bool {
+    match deser.as_array() {
+        Some(records) => records.iter().all(is_json_record),
+        None => false,
+    }
+}
+
+/** Determine if command output which is not a single JSON document is JSON Lines, starting with a JSON object
+- Colon-separated and whitespace-separated formats never start with `{` */
+fn is_json_lines(output: &str) -> bool {
+    output.trim_start().starts_with('{')
+}
+
+/** A record in JSON command output with its name, or a description of why its line is invalid */
+type JsonRecord<'a> = Result<(String, Cow<'a, serde_json::Value>), String>;
+
+/** Command output in JSON, either a single document or JSON Lines (NDJSON) with one record per line */
+enum JsonOutput<'a> {
+    Document(serde_json::Value),
+    Lines(&'a str),
+}
+
+impl JsonOutput<'_> {
+    /** Iterate over the records in the output, with their names
+    - JSON Lines are parsed one line at a time, as the records are iterated over
+    - Blank lines are ignored
+    - If a line is not a record with a name, give Err with its line number */
+    fn records(&self) -> Box<dyn Iterator<Item = JsonRecord<'_>> + '_> {
+        match self {
+            JsonOutput::Document(deser) => Box::new(
+                json_records(deser)
+                    .into_iter()
+                    .map(|(name, record)| Ok((name.to_string(), Cow::Borrowed(record)))),
+            ),
+            JsonOutput::Lines(output) => Box::new(
+                output
+                    .lines()
+                    .enumerate()
+                    .filter(|(_, line)| !line.trim().is_empty())
+                    .map(|(i, line)| parse_json_line(i + 1, line)),
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for JsonOutput<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JsonOutput::Document(deser) => write!(f, "{}", deser),
+            JsonOutput::Lines(output) => write!(f, "{}", output),
+        }
+    }
+}
+
+/** Parse a line of JSON Lines output into a record with its name
+- If the line is not valid JSON or not a record with a name, return Err with the line number */
+fn parse_json_line(number: usize, line: &str) -> JsonRecord<'static> {
+    let record: serde_json::Value = match serde_json::from_str(line) {
+        Ok(record) => record,
+        Err(e) => return Err(format!("JSON line {} is invalid: {}", number, e)),
+    };
+    match record.get("name").and_then(|x| x.as_str()) {
+        Some(name) => Ok((name.to_string(), Cow::Owned(record))),
+        None => Err(format!("JSON line {} is not a record with a name: {}", number, line)),
+    }
+}
+
+/** List the records in JSON command output with their names, in order
+- Output can be an object keyed by name, or an array of records which each have a `"name"` field
+- An array can have more than one record with the same name */
+fn json_records(deser: &serde_json::Value) -> Vec<(&str, &serde_json::Value)> {
+    match deser {
+        serde_json::Value::Array(records) => records.iter().filter_map(|x| Some((x["name"].as_str()?, x))).collect(),
+        serde_json::Value::Object(entries) => entries.iter().map(|(name, record)| (name.as_str(), record)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/** Determine if a JSON entry has `"hidden": true`, so that it is left out when its database is enumerated */
+fn is_hidden(entry: &serde_json::Value) -> bool {
+    entry.get("hidden").and_then(|x| x.as_bool()) == Some(true)
+}
+
+/** Entries of a database, with the positions of those which are hidden from enumeration */
+type Entries<T> = (Vec<T>, HashSet<usize>);
+
+/** Leave out the entries at the positions of hidden records, when listing entries for enumeration */
+fn without_hidden<T>(entries: Vec<T>, hidden: &HashSet<usize>) -> Vec<T> {
+    entries.into_iter().enumerate().filter(|(i, _)| !hidden.contains(i)).map(|(_, x)| x).collect()
+}
+
+/** Give the entries parsed from the get_all_entries output of a database, with the positions of hidden entries
+- When listing entries for enumeration, hidden entries are left out, along with their positions
+- If there are no entries, return NSS code TryAgain */
+fn listed_entries<T>(
+    database: &str,
+    entries: Vec<T>,
+    mut hidden: HashSet<usize>,
+    listing: bool,
+    debug: bool,
+) -> Response<Entries<T>> {
+    // Shouldn't ever be 0, but good to check
+    if entries.is_empty() {
+        debug_print!(format!("Returned {} data is invalid", database), debug);
+        return Response::TryAgain;
+    }
+    if listing {
+        let entries = without_hidden(entries, &hidden);
+        hidden.clear();
+        return Response::Success((entries, hidden));
+    }
+    Response::Success((entries, hidden))
+}
+
+/** List the entries of a database for enumeration, applying its `enumerate` setting
+- Hidden entries are left out, but can still be found by lookups (including those derived from get_all_entries) */
+fn enumerate_entries<T>(database: &str, all_entries: fn(bool) -> Response<Vec<T>>) -> Response<Vec<T>> {
+    validate_config!(config_deser, debug);
+    match enumerate_limit(&config_deser, database, debug) {
+        Ok(Some(0)) => {
+            debug_print!(format!("Enumeration is disabled for {}", database), debug);
+            Response::Success(Vec::new())
+        }
+        Ok(max_entries) => match all_entries(true) {
+            Response::Success(mut entries) => {
+                entries.truncate(max_entries.unwrap_or(usize::MAX));
+                Response::Success(entries)
+            }
+            response => response,
+        },
+        Err(()) => Response::Unavail,
+    }
+}
+
+/** Determine if a database builds its entries from get_all_names, which is only used if get_all_entries is not configured */
+fn uses_all_names(config: &serde_json::Value, database: &str) -> bool {
+    let functions = &config["databases"][database]["functions"];
+    functions.get("get_all_entries").is_none() && functions.get("get_all_names").is_some()
+}
+
+/** Parse the output of a get_all_names command, either a JSON array of names or one name per line
+- Comments starting with `#` and blank lines are ignored
+- If invalid, return the appropriate NSS code */
+fn parse_names(output: &str, debug: bool) -> Response<Vec<String>> {
+    let names: Vec<String> = match serde_json::from_str::<serde_json::Value>(output) {
+        Ok(serde_json::Value::Array(names)) => match names.iter().map(|x| x.as_str().map(|x| x.to_string())).collect() {
+            Some(names) => names,
+            None => {
+                debug_print!(format!("Returned names: {} must be an array of strings", output), debug);
+                return Response::TryAgain;
+            }
+        },
+        _ => output
+            .lines()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty() && !x.starts_with('#'))
+            .map(|x| x.to_string())
+            .collect(),
+    };
+    if names.is_empty() {
+        debug_print!("No names returned by get_all_names", debug);
+        return Response::NotFound;
+    }
+    Response::Success(names)
+}
+
+/** Run the get_all_names command for a database, then look up each name with get_entry_by_name to build every entry
+- Up to `parallelism` lookups (4 by default) are run at once, and the entries are kept in the order of the names
+- Names which cannot be looked up are skipped
+- When listing entries for enumeration, hidden entries are left out, and no more names are looked up once the
+  `enumerate` setting's max_entries have been found
+- Otherwise, the positions of hidden entries are returned with the entries
+- get_entry_by_name must have its own command, since deriving it would need every entry */
+fn expand_all_names<T: Send>(
+    config_deser: serde_json::Value,
+    database: &str,
+    listing: bool,
+    debug: bool,
+    get_entry_by_name: fn(String) -> Response<(T, bool)>,
+) -> Response<Entries<T>> {
+    match should_derive(&config_deser, database, "get_entry_by_name", debug) {
+        Ok(false) if config_deser["databases"][database]["functions"].get("get_entry_by_name").is_some() => {}
+        Ok(_) => {
+            debug_print!(format!("get_all_names for {} requires a get_entry_by_name command", database), debug);
+            return Response::Unavail;
+        }
+        Err(()) => return Response::Unavail,
+    }
+    let parallelism = match find_setting(&config_deser, database, "get_all_names", "parallelism") {
+        None => 4,
+        Some(parallelism) => match parallelism.as_u64() {
+            Some(parallelism) if parallelism > 0 => parallelism as usize,
+            _ => {
+                debug_print!("parallelism in config.json must be a positive integer", debug);
+                return Response::Unavail;
+            }
+        },
+    };
+    let max_entries = match listing {
+        true => match enumerate_limit(&config_deser, database, debug) {
+            Ok(max_entries) => max_entries.unwrap_or(usize::MAX),
+            Err(()) => return Response::Unavail,
+        },
+        false => usize::MAX,
+    };
+    let database = database.to_string();
+    let function = "get_all_names".to_string();
+    let code = ("".to_string(), "".to_string());
+    let run = || {
+        parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+        run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+        Response::Success(output)
+    };
+    let names = match cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+        parse_names(output, debug)
+    }) {
+        Response::Success(names) => names,
+        response => return failure(response),
+    };
+    let mut entries = Vec::new();
+    let mut hidden = HashSet::new();
+    for chunk in names.chunks(parallelism) {
+        if entries.len() >= max_entries {
+            break;
+        }
+        let responses: Vec<Response<(T, bool)>> = std::thread::scope(|scope| {
+            let lookups: Vec<_> = chunk.iter().map(|name| scope.spawn(|| get_entry_by_name(name.clone()))).collect();
+            lookups.into_iter().map(|x| x.join().unwrap_or(Response::Unavail)).collect()
+        });
+        for (name, response) in chunk.iter().zip(responses) {
+            match response {
+                Response::Success((_, true)) if listing => {}
+                Response::Success((entry, entry_hidden)) => {
+                    if entry_hidden {
+                        hidden.insert(entries.len());
+                    }
+                    entries.push(entry);
+                }
+                _ => debug_print!(format!("Skipping {} from get_all_names for {}: lookup failed", name, database), debug),
+            }
+        }
+    }
+    entries.truncate(max_entries);
+    Response::Success((entries, hidden))
+}
+
+/** Get a page of entries for enumeration, applying the `enumerate` setting of the database
+- If get_all_entries is paged and does not use the index backend, run the command for the page
+- Otherwise, return every entry as a single page */
+fn get_page<T>(
+    database: &str,
+    position: Position,
+    run_page: fn(serde_json::Value, Position, usize, bool, bool) -> Response<Page<T>>,
+    all_entries: fn(bool) -> Response<Vec<T>>,
+) -> Response<Page<T>> {
+    validate_config!(config_deser, debug);
+    let max_entries = match enumerate_limit(&config_deser, database, debug) {
+        Ok(max_entries) => max_entries.unwrap_or(usize::MAX),
+        Err(()) => return Response::Unavail,
+    };
+    // Entries built from get_all_names are never paged
+    let paged = match uses_all_names(&config_deser, database) {
+        true => Ok(None),
+        false => page_size(&config_deser, database, debug),
+    };
+    let page = match (paged, index::use_index(&config_deser, database, "get_all_entries", debug)) {
+        _ if max_entries == 0 => {
+            debug_print!(format!("Enumeration is disabled for {}", database), debug);
+            return Response::Success(Page {entries: Vec::new(), hidden: HashSet::new(), next: None});
+        }
+        (Ok(Some(limit)), Ok(false)) => run_page(config_deser, position.clone(), limit, true, debug),
+        (Ok(_), Ok(_)) => match all_entries(true) {
+            Response::Success(entries) => Response::Success(Page {entries, hidden: HashSet::new(), next: None}),
+            response => failure(response),
+        },
+        _ => Response::Unavail,
+    };
+    match page {
+        Response::Success(mut page) => {
+            // Stop once max_entries have been listed, including those in earlier pages
+            let remaining = max_entries.saturating_sub(position.listed);
+            if page.entries.len() >= remaining {
+                page.entries.truncate(remaining);
+                page.next = None;
+            }
+            Response::Success(page)
+        }
+        response => response,
+    }
+}
+
+/** Convert a group entry to an index record */
+fn group_record(x: Group, hidden: bool) -> index::Record {
+    index::Record {
+        name: x.name.clone(),
+        id: Some(x.gid),
+        hidden,
+        fields: vec![x.name, x.passwd, x.gid.to_string(), x.members.join(",")],
+    }
+}
+
+/** Convert a passwd entry to an index record */
+fn passwd_record(x: Passwd, hidden: bool) -> index::Record {
+    index::Record {
+        name: x.name.clone(),
+        id: Some(x.uid),
+        hidden,
+        fields: vec![
+            x.name,
+            x.passwd,
+            x.uid.to_string(),
+            x.gid.to_string(),
+            x.gecos,
+            x.dir,
+            x.shell,
+        ],
+    }
+}
+
+/** Convert a shadow entry to an index record */
+fn shadow_record(x: Shadow, hidden: bool) -> index::Record {
+    index::Record {
+        name: x.name.clone(),
+        id: None,
+        hidden,
+        fields: vec![
+            x.name,
+            x.passwd,
+            x.last_change.to_string(),
+            x.change_min_days.to_string(),
+            x.change_max_days.to_string(),
+            x.change_warn_days.to_string(),
+            x.change_inactive_days.to_string(),
+            x.expire_date.to_string(),
+            x.reserved.to_string(),
+        ],
+    }
+}
+
+/** Split the addresses of a host into one Host object per address family, since a hostent only has one */
+fn host_entries(name: String, aliases: Vec<String>, addresses: Vec<IpAddr>) -> Vec<Host> {
+    let mut v4: Vec<Ipv4Addr> = Vec::new();
+    let mut v6: Vec<Ipv6Addr> = Vec::new();
+    for address in addresses {
+        match address {
+            IpAddr::V4(x) => v4.push(x),
+            IpAddr::V6(x) => v6.push(x),
+        }
+    }
+    let mut hosts = Vec::new();
+    if !v4.is_empty() {
+        hosts.push(Host { name: name.clone(), aliases: aliases.clone(), addresses: Addresses::V4(v4) });
+    }
+    if !v6.is_empty() {
+        hosts.push(Host { name, aliases, addresses: Addresses::V6(v6) });
+    }
+    hosts
+}
+
+/** Combine hosts with the same name and address family, keeping the order they were first seen in */
+fn merge_hosts(hosts: Vec<Host>) -> Vec<Host> {
+    let mut merged: Vec<Host> = Vec::new();
+    let mut positions: HashMap<(String, &str), usize> = HashMap::new();
+    for host in hosts {
+        let key = (host.name.clone(), host_family(&host));
+        match positions.get(&key) {
+            Some(i) => {
+                let existing = &mut merged[*i];
+                for alias in host.aliases {
+                    if !existing.aliases.contains(&alias) {
+                        existing.aliases.push(alias);
+                    }
+                }
+                match (&mut existing.addresses, host.addresses) {
+                    (Addresses::V4(x), Addresses::V4(y)) => x.extend(y),
+                    (Addresses::V6(x), Addresses::V6(y)) => x.extend(y),
+                    _ => {}
+                }
+            }
+            None => {
+                positions.insert(key, merged.len());
+                merged.push(host);
+            }
+        }
+    }
+    merged
+}
+
+/** Get the address family of a host, as used for the <$family> code */
+fn host_family(host: &Host) -> &'static str {
+    match host.addresses {
+        Addresses::V4(_) => "ipv4",
+        Addresses::V6(_) => "ipv6",
+    }
+}
+
+/** Determine if a host has the given name or alias (ignoring case), and addresses in the given family
+- If the family is blank, any family matches */
+fn host_matches(host: &Host, name: &str, family: &str) -> bool {
+    (host.name.eq_ignore_ascii_case(name) || host.aliases.iter().any(|x| x.eq_ignore_ascii_case(name)))
+        && (family.is_empty() || family == host_family(host))
+}
+
+/** Determine if a host has the given address */
+fn host_has_addr(host: &Host, addr: &IpAddr) -> bool {
+    match (&host.addresses, addr) {
+        (Addresses::V4(x), IpAddr::V4(y)) => x.contains(y),
+        (Addresses::V6(x), IpAddr::V6(y)) => x.contains(y),
+        _ => false,
+    }
+}
+
+/** Determine if an entry has the given name, either as its name or one of its aliases */
+fn has_name(name: &str, aliases: &[String], query: &str) -> bool {
+    name == query || aliases.iter().any(|x| x == query)
+}
+
+/** Convert a gshadow entry to an index record */
+fn gshadow_record(x: GShadow, hidden: bool) -> index::Record {
+    index::Record {
+        name: x.name.clone(),
+        id: None,
+        hidden,
+        fields: vec![x.name, x.passwd, x.administrators.join(","), x.members.join(",")],
+    }
+}
+
+/** Update the index file of a database, and return the number of entries in it
+- If get_changes_since is configured and a token was saved by the last refresh, only the changes since then are
+  applied to the existing index
+- Otherwise, or if the token is rejected, get_all_entries is run to rebuild the whole index
+- Commands are always run (or their output read from the disk cache), even if the `index` backend is configured
+- If the database is not supported, return NSS code NotFound
+- If the index file cannot be written, return NSS code Unavail */
+pub fn refresh_index(database: &str) -> Response<usize> {
+    validate_config!(config_deser, debug);
+    match database {
+        "group" => refresh_database(
+            database,
+            config_deser,
+            debug,
+            ShimGroup::run_all_entries,
+            ShimGroup::run_changes_since,
+            group_record,
+        ),
+        "passwd" => refresh_database(
+            database,
+            config_deser,
+            debug,
+            ShimPasswd::run_all_entries,
+            ShimPasswd::run_changes_since,
+            passwd_record,
+        ),
+        "shadow" => refresh_database(
+            database,
+            config_deser,
+            debug,
+            ShimShadow::run_all_entries,
+            ShimShadow::run_changes_since,
+            shadow_record,
+        ),
+        "gshadow" => refresh_database(
+            database,
+            config_deser,
+            debug,
+            ShimGShadow::run_all_entries,
+            ShimGShadow::run_changes_since,
+            gshadow_record,
+        ),
+        _ => {
+            debug_print!(format!("Database {} does not support index files", database), debug);
+            Response::NotFound
+        }
+    }
+}
+
+/** Update the index file of a single database, as described for refresh_index */
+fn refresh_database<T>(
+    database: &str,
+    config_deser: serde_json::Value,
+    debug: bool,
+    run_all_entries: fn(serde_json::Value, bool) -> Response<Entries<T>>,
+    run_changes_since: fn(serde_json::Value, String, bool) -> Response<index::Changes<T>>,
+    to_record: fn(T, bool) -> index::Record,
+) -> Response<usize> {
+    let incremental = config_deser["databases"][database]["functions"]
+        .get("get_changes_since")
+        .is_some();
+    let mut token = None;
+    if incremental {
+        if let Some(saved_token) = index::read_token(database) {
+            match run_changes_since(config_deser.clone(), saved_token, debug) {
+                Response::Success(changes) if !changes.resync => {
+                    match index::read_records(database, debug) {
+                        Some(mut records) => {
+                            apply_changes(&mut records, changes.upserts, &changes.hidden, &changes.deletions, to_record);
+                            let token = Some(changes.token.as_str()).filter(|x| !x.is_empty());
+                            return write_index_and_token(database, &records, token, debug);
+                        }
+                        None => debug_print!(
+                            format!("Unable to apply changes to index for {}, running get_all_entries", database),
+                            debug
+                        ),
+                    }
+                }
+                Response::Success(_) => debug_print!(
+                    format!("Token rejected by get_changes_since for {}, running get_all_entries", database),
+                    debug
+                ),
+                response => return failure(response),
+            }
+        }
+        // Get the current token before running get_all_entries, so that no changes are missed in between
+        // Without a usable token, none is saved so that the next refresh runs get_all_entries again
+        token = match run_changes_since(config_deser.clone(), "".to_string(), debug) {
+            Response::Success(changes) if !changes.resync && !changes.token.is_empty() => Some(changes.token),
+            Response::Success(_) => {
+                debug_print!(format!("No token given by get_changes_since for {}", database), debug);
+                None
+            }
+            response => return failure(response),
+        };
+    }
+    let records: Vec<index::Record> = match run_all_entries(config_deser, debug) {
+        Response::Success((entries, hidden)) => {
+            entries.into_iter().enumerate().map(|(i, x)| to_record(x, hidden.contains(&i))).collect()
+        }
+        response => return failure(response),
+    };
+    write_index_and_token(database, &records, token.as_deref(), debug)
+}
+
+/** Apply the changes given by get_changes_since to the records of an index file
+- Deleted records are removed, and upserts replace the record with the same name or are added after the others
+- Hidden has the positions of the upserts which are hidden from enumeration */
+fn apply_changes<T>(
+    records: &mut Vec<index::Record>,
+    upserts: Vec<T>,
+    hidden: &HashSet<usize>,
+    deletions: &[String],
+    to_record: fn(T, bool) -> index::Record,
+) {
+    let deletions: HashSet<&String> = deletions.iter().collect();
+    records.retain(|x| !deletions.contains(&x.name));
+    let mut positions: HashMap<String, usize> = records.iter().enumerate().map(|(i, x)| (x.name.clone(), i)).collect();
+    for record in upserts.into_iter().enumerate().map(|(i, x)| to_record(x, hidden.contains(&i))) {
+        match positions.get(&record.name) {
+            Some(i) => records[*i] = record,
+            None => {
+                positions.insert(record.name.clone(), records.len());
+                records.push(record);
+            }
+        }
+    }
+}
+
+/** Write the index file of a database, followed by the token to use for the next incremental refresh
+- Without a token, any saved token is removed so that the next refresh is a full one */
+fn write_index_and_token(
+    database: &str,
+    records: &[index::Record],
+    token: Option<&str>,
+    debug: bool,
+) -> Response<usize> {
+    match index::write_index(database, records).and_then(|_| index::write_token(database, token)) {
+        Ok(()) => Response::Success(records.len()),
+        Err(e) => {
+            debug_print!(format!("Unable to write index file for {}: {}", database, e), debug);
+            Response::Unavail
+        }
+    }
+}
+
+/// Number of lines of command output which can be read ahead of parsing, when the output is read one line at a time
+const OUTPUT_LINES_AHEAD: usize = 64;
+
+/** Run a command and capture its stdout, killing it if it runs for longer than the timeout
+- Like Command::output(), stdin is empty and stderr is not shown
+- The timeout covers the time until the command closes its stdout, normally by exiting
+- If the timeout is reached, return an error of kind TimedOut */
+fn output_with_timeout(command: &mut Command, timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdout = match child.stdout.take() {
+        Some(stdout) => stdout,
+        None => return Err(io::Error::new(io::ErrorKind::Other, "stdout was not captured")),
+    };
+    // Read the output in another thread so that a full pipe cannot block the child, and wait for it with the timeout
+    let (sender, receiver) = mpsc::channel();
+    let reader = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = sender.send(stdout.read_to_end(&mut buffer).map(|_| buffer));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => {
+            let _ = reader.join();
+            // The command has closed its stdout, so it is normally exiting, but the lookup does not wait for it
+            match child.try_wait() {
+                Ok(Some(_)) => {}
+                _ => drop(thread::spawn(move || child.wait())),
+            }
+            result
+        }
+        Err(e) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            // The reader finishes once stdout is closed, unless a process started by the command still holds it
+            // open, in which case the reader is detached and finishes when that process exits
+            if receiver.recv_timeout(Duration::from_millis(100)).is_ok() {
+                let _ = reader.join();
+            }
+            match e {
+                mpsc::RecvTimeoutError::Timeout => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("timed out after {:?}", timeout),
+                )),
+                mpsc::RecvTimeoutError::Disconnected => {
+                    Err(io::Error::new(io::ErrorKind::Other, "unable to read stdout"))
+                }
+            }
+        }
+    }
+}
+
+/** The stdout of a running command, read one line at a time
+- Lines are read in another thread and passed through a bounded channel, so that the output is only read as fast as it
+  is parsed, rather than all at once
+- With a timeout, reading fails with an error of kind TimedOut once the command has run for longer than the timeout
+- If the output is not read to the end, the command is killed when this is dropped */
+struct OutputLines {
+    child: Option<Child>,
+    receiver: mpsc::Receiver<io::Result<String>>,
+    timeout: Option<Duration>,
+    started: Instant,
+    finished: bool,
+}
+
+impl OutputLines {
+    /** Start a command and read its stdout
+    - Like Command::output(), stdin is empty and stderr is not shown */
+    fn spawn(command: &mut Command, timeout: Option<Duration>) -> io::Result<OutputLines> {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = match child.stdout.take() {
+            Some(stdout) => stdout,
+            None => return Err(io::Error::new(io::ErrorKind::Other, "stdout was not captured")),
+        };
+        let (sender, receiver) = mpsc::sync_channel(OUTPUT_LINES_AHEAD);
+        // The reader stops at the end of stdout, at the first error, or once the lines are no longer wanted
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let error = line.is_err();
+                if sender.send(line).is_err() || error {
+                    break;
+                }
+            }
+        });
+        Ok(OutputLines {
+            child: Some(child),
+            receiver,
+            timeout,
+            started: Instant::now(),
+            finished: false,
+        })
+    }
+}
+
+impl Iterator for OutputLines {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        if self.finished {
+            return None;
+        }
+        let line = match self.timeout {
+            Some(timeout) => match self.receiver.recv_timeout(timeout.saturating_sub(self.started.elapsed())) {
+                Ok(line) => Some(line),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("timed out after {:?}", timeout),
+                    )))
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => None,
+            },
+            None => self.receiver.recv().ok(),
+        };
+        // The command has closed its stdout, so it is normally exiting
+        self.finished = line.is_none();
+        line
+    }
+}
+
+impl Drop for OutputLines {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            if !self.finished {
+                let _ = child.kill();
+            }
+            // The lookup does not wait for the command to exit
+            match child.try_wait() {
+                Ok(Some(_)) => {}
+                _ => drop(thread::spawn(move || child.wait())),
+            }
+        }
+    }
+}
+
+/** Command output read by stream_json_lines */
+enum StreamedOutput<T> {
+    /// JSON Lines, with the entries which were parsed one line at a time as the output was read
+    Records(Entries<T>),
+    /// Output in another format, which was read whole (and trimmed) to be parsed as usual
+    Whole(String),
+}
+
+/** Read the output of a command, parsing it one line at a time as it is read if it is JSON Lines
+- The first line which is not blank decides the format: JSON Lines start with a record which has a name
+- Each record is parsed by `parse` with its name, and the positions of hidden records are kept
+- Blank lines are ignored, but are still counted in line numbers
+- If a line is not a record with a name, return NSS code TryAgain and log its line number
+- If the output cannot be read or the timeout is reached, return NSS code TryAgain */
+fn stream_json_lines<T>(
+    lines: OutputLines,
+    debug: bool,
+    mut parse: impl FnMut(String, Cow<'static, serde_json::Value>) -> Response<T>,
+) -> Response<StreamedOutput<T>> {
+    let mut entries = Vec::new();
+    let mut hidden = HashSet::new();
+    let mut streaming = None;
+    let mut whole = String::new();
+    for (i, line) in lines.enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                debug_print!(format!("Runtime error for command: {}", e), debug);
+                return Response::TryAgain;
+            }
+        };
+        if streaming == Some(false) {
+            whole.push('\n');
+            whole.push_str(&line);
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = parse_json_line(i + 1, &line);
+        if streaming.is_none() {
+            streaming = Some(record.is_ok());
+            if record.is_err() {
+                whole = line;
+                continue;
+            }
+        }
+        let (name, record) = match record {
+            Ok(record) => record,
+            Err(e) => {
+                debug_print!(e, debug);
+                return Response::TryAgain;
+            }
+        };
+        if is_hidden(&record) {
+            hidden.insert(entries.len());
+        }
+        match parse(name, record) {
+            Response::Success(entry) => entries.push(entry),
+            response => return failure(response),
+        }
+    }
+    match streaming {
+        Some(true) => Response::Success(StreamedOutput::Records((entries, hidden))),
+        _ => Response::Success(StreamedOutput::Whole(whole.trim().to_string())),
+    }
+}
+
+///////////////////
+//  Group hooks  //
+///////////////////
+
+struct ShimGroup;
+group_hooks!(shim, ShimGroup);
+
+impl GroupHooks for ShimGroup {
+    fn get_all_entries() -> Response<Vec<Group>> {
+        enumerate_entries("group", Self::all_entries)
+    }
+
+    fn get_entry_by_gid(gid: libc::gid_t) -> Response<Group> {
+        validate_config!(config_deser, debug);
+        let database = "group".to_string();
+        let function = "get_entry_by_gid".to_string();
+        // Set the code for gid
+        let code = ("<$gid>".to_string(), gid.to_string());
+        read_index!(config_deser, database, function, index::Key::Id(gid), parse_group_format, debug);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Group| x.gid == gid, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                validate_response!(output, option, debug);
+                parse_options!(config_deser, database, function, options, debug);
+                match option {
+                    Some(deser) => {
+                        // Return after first loop iteration since this is supposed to be a single entry
+                        for record in deser.records() {
+                            let (group_entry, record) = valid_record!(record, debug);
+                            parse_group_json!(group_entry, record, group, options.validation, &options.defaults, debug);
+                            if group.gid != gid {
+                                debug_print!(
+                                    format!(
+                                        "Returned group data: {} does not contain a matching gid: {}",
+                                        deser,
+                                        gid
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            return Response::Success(group);
+                        }
+                    }
+                    _ => {
+                        for line in colon_lines(output) {
+                            let fields = split_colons(line, 4, options.escaped_colons);
+                            if fields.len() < 4 {
+                                debug_print!(
+                                    format!(
+                                        "Returned group data: {} does not match expected unix form",
+                                        line
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            let mut entries = fields.iter().map(|x| x.as_str());
+                            return Response::Success(parse_group_format!(entries, options.validation, &options.defaults, debug));
+                        }
+                        // Shouldn't happen since this implies the text had no lines
+                        debug_print!(
+                            "Returned group data does not contain valid strings".to_string(),
+                            debug
+                        );
+                        return Response::TryAgain;
+                    }
+                };
+                debug_print!(format!("gid: {} not found in group", gid), debug);
+                Response::NotFound
+            })
+        })
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Group> {
+        match Self::entry_by_name(name) {
+            Response::Success((group, _)) => Response::Success(group),
+            response => failure(response),
+        }
+    }
+}
+
+impl PagedHooks<Group> for ShimGroup {
+    fn get_page(position: Position) -> Response<Page<Group>> {
+        get_page("group", position, Self::run_page, Self::all_entries)
+    }
+}
+
+impl ShimGroup {
+    /** Look up a group entry by name, with whether it is hidden from enumeration */
+    fn entry_by_name(name: String) -> Response<(Group, bool)> {
+        validate_config!(config_deser, debug);
+        let database = "group".to_string();
+        let function = "get_entry_by_name".to_string();
+        let code = ("<$name>".to_string(), name.clone());
+        read_index!(config_deser, database, function, index::Key::Name(&name), parse_group_format, hidden, debug);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Group| x.name == name, hidden, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                validate_response!(output, option, debug);
+                parse_options!(config_deser, database, function, options, debug);
+                match option {
+                    Some(deser) => {
+                        for record in deser.records() {
+                            let (group_entry, record) = valid_record!(record, debug);
+                            if code.1 != group_entry {
+                                debug_print!(
+                                    format!(
+                                        "Requested name: {} does not match returned name: {}",
+                                        code.1,
+                                        group_entry.to_string()
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            parse_group_json!(group_entry, record, group, options.validation, &options.defaults, debug);
+                            return Response::Success((group, is_hidden(&record)));
+                        }
+                    }
+                    _ => {
+                        for line in colon_lines(output) {
+                            let fields = split_colons(line, 4, options.escaped_colons);
+                            if fields.len() < 4 {
+                                debug_print!(
+                                    format!(
+                                        "Returned group data: {} does not match expected unix form",
+                                        line
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            let mut entries = fields.iter().map(|x| x.as_str());
+                            return Response::Success((parse_group_format!(entries, options.validation, &options.defaults, debug), false));
+                        }
+                        debug_print!(
+                            format!(
+                                "Returned group data does not match expected unix form: {}",
+                                output.trim()
+                            ),
+                            debug
+                        );
+                        return Response::TryAgain;
+                    }
+                };
+                debug_print!(format!("Name: {} not found in group", name), debug);
+                Response::NotFound
+            })
+        })
+    }
+
+    /** Get every group entry, from the index file if configured
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Group>> {
+        // Ensure the configuration is usable
+        validate_config!(config_deser, debug);
+        let database = "group".to_string();
+        let function = "get_all_entries".to_string();
+        // Read the entries from the index file instead of running a command, if configured
+        read_index!(config_deser, database, function, parse_group_format, listing, debug);
+        match Self::run_entries(config_deser, listing, debug) {
+            Response::Success((entries, _)) => Response::Success(entries),
+            response => failure(response),
+        }
+    }
+
+    /** Run the get_all_entries command for one page of group, if it is paged */
+    fn run_page(
+        config_deser: serde_json::Value,
+        position: Position,
+        limit: usize,
+        listing: bool,
+        debug: bool,
+    ) -> Response<Page<Group>> {
+        let database = "group".to_string();
+        run_page!(config_deser, database, position, limit, parse_group_json, parse_group_format, 3, 4, listing, debug)
+    }
+
+    /** Run the get_all_entries command for group and parse the output, with the positions of hidden entries
+    - This is used to build index files, so it never reads from the index */
+    fn run_all_entries(config_deser: serde_json::Value, debug: bool) -> Response<Entries<Group>> {
+        Self::run_entries(config_deser, false, debug)
+    }
+
+    /** Run the get_all_entries command for group and parse the output, with the positions of hidden entries
+    - When listing entries for enumeration, hidden entries are left out, along with their positions */
+    fn run_entries(config_deser: serde_json::Value, listing: bool, debug: bool) -> Response<Entries<Group>> {
+        let database = "group".to_string();
+        // Look up each name from get_all_names instead, if configured
+        if uses_all_names(&config_deser, &database) {
+            return expand_all_names(config_deser, &database, listing, debug, Self::entry_by_name);
+        }
+        // Combine every page, if get_all_entries is paged
+        match page_size(&config_deser, &database, debug) {
+            Ok(Some(limit)) => {
+                return paged::collect_pages(|position| Self::run_page(config_deser.clone(), position, limit, listing, debug))
+            }
+            Ok(None) => {}
+            Err(()) => return Response::Unavail,
+        }
+        let function = "get_all_entries".to_string();
+        // Since this function does not have a uid/gid/name parameter, set the code as blank
+        let code = ("".to_string(), "".to_string());
+        // JSON Lines are parsed as they are read, unless the output is needed whole
+        stream_all_entries!(config_deser, database, function, code, parse_group_json, listing, streamed, debug);
+        let run = || {
+            if let Some(output) = streamed {
+                return Response::Success(output);
+            }
+            // Get the command data for this particular db and function
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            // Run the command defined in the config and capture the output as String
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        // Use a cached copy of the output if available, otherwise run the command
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            // Validate the command output and determine if it is JSON or not
+            validate_response!(output, option, debug);
+            parse_options!(config_deser, database, function, options, debug);
+            let mut group_vec: Vec<Group> = Vec::new();
+            let mut hidden: HashSet<usize> = HashSet::new();
+            match option {
+                Some(deser) => {
+                    // Parse as JSON
+                    for record in deser.records() {
+                        let (group_entry, record) = valid_record!(record, debug);
+                        if is_hidden(&record) {
+                            hidden.insert(group_vec.len());
+                        }
+                        parse_group_json!(group_entry, record, group, options.validation, &options.defaults, debug);
+                        group_vec.push(group);
+                    }
+                }
+                _ => {
+                    // Parse as unix-style file format
+                    for line in colon_lines(output) {
+                        let fields = split_colons(line, 4, options.escaped_colons);
+                        if fields.len() < 4 {
+                            debug_print!(
+                                format!(
+                                    "Returned group data: {} does not match expected unix form",
+                                    line
+                                ),
+                                debug
+                            );
+                            return Response::TryAgain;
+                        }
+                        let mut entries = fields.iter().map(|x| x.as_str());
+                        group_vec.push(parse_group_format!(entries, options.validation, &options.defaults, debug));
+                    }
+                }
+            };
+            // Shouldn't ever be 0, but good to check
+            listed_entries(&database, group_vec, hidden, listing, debug)
+        })
+    }
+
+    /** Run the get_changes_since command for group with the token saved by the last refresh */
+    fn run_changes_since(config_deser: serde_json::Value, token: String, debug: bool) -> Response<index::Changes<Group>> {
+        let database = "group".to_string();
+        run_changes_since!(config_deser, database, token, parse_group_json, debug)
+    }
+}
+
+////////////////////////
+//  Initgroups hooks  //
+////////////////////////
+
+struct ShimInitgroups;
+initgroups_hooks!(shim, ShimInitgroups);
+
+impl InitgroupsHooks for ShimInitgroups {
+    fn get_groups_by_member(name: String, gid: libc::gid_t) -> Response<Vec<libc::gid_t>> {
+        validate_config!(config_deser, debug);
+        let database = "group".to_string();
+        let function = "get_groups_by_member".to_string();
+        // Set the codes for the name and primary gid of the user
+        let code = ("<$name>".to_string(), name.clone());
+        let gid_code = ("<$gid>".to_string(), gid.to_string());
+        // Find the groups listing the user as a member in get_all_entries if there is no command or index to use
+        let derive = match index::use_index(&config_deser, &database, &function, debug) {
+            Ok(true) => true,
+            Ok(false) => match should_derive(&config_deser, &database, &function, debug) {
+                Ok(derive) => derive,
+                Err(()) => return Response::Unavail,
+            },
+            Err(()) => return Response::Unavail,
+        };
+        if derive {
+            debug_print!(format!("Deriving {} {} from get_all_entries", database, function), debug);
+            return match ShimGroup::all_entries(false) {
+                Response::Success(groups) => Response::Success(
+                    groups
+                        .into_iter()
+                        .filter(|x| x.members.contains(&name))
+                        .map(|x| x.gid)
+                        .collect(),
+                ),
+                response => failure(response),
+            };
+        }
+        // The command is given both codes, so results are cached for both (and flushed by the name)
+        let query = format!("{}/{}", name, gid);
+        cache::with_negative_cache(&config_deser, &database, &function, &query, debug, || {
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                let (command, env_vars) = replace_code(command, env_vars, &gid_code);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &query, debug, run, |output| {
+                parse_options!(config_deser, database, function, options, debug);
+                let mut gid_vec: Vec<libc::gid_t> = Vec::new();
+                // If there is a status envelope, return its NSS code or continue with its entries
+                let parsed = match serde_json::from_str::<serde_json::Value>(output).ok().map(|x| open_envelope(x, debug)) {
+                    Some(Ok(deser)) => Some(deser),
+                    Some(Err(response)) => return failure(response),
+                    None => None,
+                };
+                match parsed {
+                    Some(serde_json::Value::Array(gids)) if !gids.iter().any(|x| x.is_object()) => {
+                        // Parse as a JSON list of gids
+                        for gid_entry in gids {
+                            match gid_entry.as_u64() {
+                                Some(x) if x <= u32::MAX as u64 => gid_vec.push(x as u32),
+                                _ => {
+                                    debug_print!(format!("Returned gid: {} is not valid", gid_entry), debug);
+                                    return Response::TryAgain;
+                                }
+                            }
+                        }
+                    }
+                    Some(deser) if (deser.is_object() && !is_json_record(&deser)) || is_json_records(&deser) => {
+                        // Parse as JSON group entries, keyed by name or as an array of records
+                        for (group_entry, record) in json_records(&deser) {
+                            parse_group_json!(group_entry, record, group, options.validation, &options.defaults, debug);
+                            gid_vec.push(group.gid);
+                        }
+                    }
+                    _ if is_json_lines(output) => {
+                        // Parse as JSON Lines of group entries
+                        for record in JsonOutput::Lines(output).records() {
+                            let (group_entry, record) = valid_record!(record, debug);
+                            parse_group_json!(group_entry, record, group, options.validation, &options.defaults, debug);
+                            gid_vec.push(group.gid);
+                        }
+                    }
+                    _ => {
+                        // Parse as lines of unix-style group entries or whitespace-separated gids
+                        for line in colon_lines(output) {
+                            if line.contains(':') {
+                                let fields = split_colons(line, 4, options.escaped_colons);
+                                if fields.len() < 4 {
+                                    debug_print!(
+                                        format!(
+                                            "Returned group data: {} does not match expected unix form",
+                                            line
+                                        ),
+                                        debug
+                                    );
+                                    return Response::TryAgain;
+                                }
+                                let mut entries = fields.iter().map(|x| x.as_str());
+                                gid_vec.push(parse_group_format!(entries, options.validation, &options.defaults, debug).gid);
+                                continue;
+                            }
+                            for gid_entry in line.split_whitespace() {
+                                match gid_entry.parse::<u32>() {
+                                    Ok(x) => gid_vec.push(x),
+                                    Err(_) => {
+                                        debug_print!(format!("Returned gid: {} is not valid", gid_entry), debug);
+                                        return Response::TryAgain;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                };
+                if gid_vec.is_empty() {
+                    debug_print!(format!("No groups found for member: {}", name), debug);
+                    return Response::NotFound;
+                }
+                Response::Success(gid_vec)
+            })
+        })
+    }
+}
+
+//////////////////
+// Passwd hooks //
+//////////////////
+
+struct ShimPasswd;
+passwd_hooks!(shim, ShimPasswd);
+
+impl PasswdHooks for ShimPasswd {
+    fn get_all_entries() -> Response<Vec<Passwd>> {
+        enumerate_entries("passwd", Self::all_entries)
+    }
+
+    fn get_entry_by_uid(uid: libc::uid_t) -> Response<Passwd> {
+        validate_config!(config_deser, debug);
+        let database = "passwd".to_string();
+        let function = "get_entry_by_uid".to_string();
+        let code = ("<$uid>".to_string(), uid.to_string());
+        read_index!(config_deser, database, function, index::Key::Id(uid), parse_passwd_format, debug);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Passwd| x.uid == uid, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                validate_response!(output, option, debug);
+                parse_options!(config_deser, database, function, options, debug);
+                match option {
+                    Some(deser) => {
+                        for record in deser.records() {
+                            let (passwd_entry, record) = valid_record!(record, debug);
+                            parse_passwd_json!(passwd_entry, record, passwd, options.validation, &options.defaults, debug);
+                            if passwd.uid != uid {
+                                debug_print!(
+                                    format!(
+                                        "Returned passwd data: {} does not contain a matching uid: {}",
+                                        deser,
+                                        uid
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            return Response::Success(passwd);
+                        }
+                    }
+                    _ => {
+                        for line in colon_lines(output) {
+                            let fields = split_colons(line, 7, options.escaped_colons);
+                            if fields.len() < 7 {
+                                debug_print!(
+                                    format!(
+                                        "Returned passwd data: {} does not match expected unix form",
+                                        line
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            let mut entries = fields.iter().map(|x| x.as_str());
+                            return Response::Success(parse_passwd_format!(entries, options.validation, &options.defaults, debug));
+                        }
+                    }
+                };
+                debug_print!(format!("uid: {} not found in passwd", uid), debug);
+                Response::NotFound
+            })
+        })
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Passwd> {
+        match Self::entry_by_name(name) {
+            Response::Success((passwd, _)) => Response::Success(passwd),
+            response => failure(response),
+        }
+    }
+}
+
+impl PagedHooks<Passwd> for ShimPasswd {
+    fn get_page(position: Position) -> Response<Page<Passwd>> {
+        get_page("passwd", position, Self::run_page, Self::all_entries)
+    }
+}
+
+impl ShimPasswd {
+    /** Look up a passwd entry by name, with whether it is hidden from enumeration */
+    fn entry_by_name(name: String) -> Response<(Passwd, bool)> {
+        validate_config!(config_deser, debug);
+        let database = "passwd".to_string();
+        let function = "get_entry_by_name".to_string();
+        let code = ("<$name>".to_string(), name.clone());
+        read_index!(config_deser, database, function, index::Key::Name(&name), parse_passwd_format, hidden, debug);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Passwd| x.name == name, hidden, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                validate_response!(output, option, debug);
+                parse_options!(config_deser, database, function, options, debug);
+                match option {
+                    Some(deser) => {
+                        for record in deser.records() {
+                            let (passwd_entry, record) = valid_record!(record, debug);
+                            if code.1 != passwd_entry {
+                                debug_print!(
+                                    format!(
+                                        "Requested name: {} does not match returned name: {}",
+                                        code.1,
+                                        passwd_entry.to_string()
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            parse_passwd_json!(passwd_entry, record, passwd, options.validation, &options.defaults, debug);
+                            return Response::Success((passwd, is_hidden(&record)));
+                        }
+                    }
+                    _ => {
+                        for line in colon_lines(output) {
+                            let fields = split_colons(line, 7, options.escaped_colons);
+                            if fields.len() < 7 {
+                                debug_print!(
+                                    format!(
+                                        "Returned passwd data: {} does not match expected unix form",
+                                        line
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            let mut entries = fields.iter().map(|x| x.as_str());
+                            return Response::Success((parse_passwd_format!(entries, options.validation, &options.defaults, debug), false));
+                        }
+                    }
+                };
+                debug_print!(format!("Name: {} not found in passwd", name), debug);
+                Response::NotFound
+            })
+        })
+    }
+
+    /** Get every passwd entry, from the index file if configured
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Passwd>> {
+        validate_config!(config_deser, debug);
+        let database = "passwd".to_string();
+        let function = "get_all_entries".to_string();
+        // Read the entries from the index file instead of running a command, if configured
+        read_index!(config_deser, database, function, parse_passwd_format, listing, debug);
+        match Self::run_entries(config_deser, listing, debug) {
+            Response::Success((entries, _)) => Response::Success(entries),
+            response => failure(response),
+        }
+    }
+
+    /** Run the get_all_entries command for one page of passwd, if it is paged */
+    fn run_page(
+        config_deser: serde_json::Value,
+        position: Position,
+        limit: usize,
+        listing: bool,
+        debug: bool,
+    ) -> Response<Page<Passwd>> {
+        let database = "passwd".to_string();
+        run_page!(config_deser, database, position, limit, parse_passwd_json, parse_passwd_format, 6, 7, listing, debug)
+    }
+
+    /** Run the get_all_entries command for passwd and parse the output, with the positions of hidden entries
+    - This is used to build index files, so it never reads from the index */
+    fn run_all_entries(config_deser: serde_json::Value, debug: bool) -> Response<Entries<Passwd>> {
+        Self::run_entries(config_deser, false, debug)
+    }
+
+    /** Run the get_all_entries command for passwd and parse the output, with the positions of hidden entries
+    - When listing entries for enumeration, hidden entries are left out, along with their positions */
+    fn run_entries(config_deser: serde_json::Value, listing: bool, debug: bool) -> Response<Entries<Passwd>> {
+        let database = "passwd".to_string();
+        // Look up each name from get_all_names instead, if configured
+        if uses_all_names(&config_deser, &database) {
+            return expand_all_names(config_deser, &database, listing, debug, Self::entry_by_name);
+        }
+        // Combine every page, if get_all_entries is paged
+        match page_size(&config_deser, &database, debug) {
+            Ok(Some(limit)) => {
+                return paged::collect_pages(|position| Self::run_page(config_deser.clone(), position, limit, listing, debug))
+            }
+            Ok(None) => {}
+            Err(()) => return Response::Unavail,
+        }
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        // JSON Lines are parsed as they are read, unless the output is needed whole
+        stream_all_entries!(config_deser, database, function, code, parse_passwd_json, listing, streamed, debug);
+        let run = || {
+            if let Some(output) = streamed {
+                return Response::Success(output);
+            }
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            validate_response!(output, option, debug);
+            parse_options!(config_deser, database, function, options, debug);
+            let mut passwd_vec: Vec<Passwd> = Vec::new();
+            let mut hidden: HashSet<usize> = HashSet::new();
+            match option {
+                Some(deser) => {
+                    for record in deser.records() {
+                        let (passwd_entry, record) = valid_record!(record, debug);
+                        if is_hidden(&record) {
+                            hidden.insert(passwd_vec.len());
+                        }
+                        parse_passwd_json!(passwd_entry, record, passwd, options.validation, &options.defaults, debug);
+                        passwd_vec.push(passwd);
+                    }
+                }
+                _ => {
+                    for line in colon_lines(output) {
+                        let fields = split_colons(line, 7, options.escaped_colons);
+                        if fields.len() < 7 {
+                            debug_print!(
+                                format!(
+                                    "Returned passwd data: {} does not match expected unix form",
+                                    line
+                                ),
+                                debug
+                            );
+                            return Response::TryAgain;
+                        }
+                        let mut entries = fields.iter().map(|x| x.as_str());
+                        passwd_vec.push(parse_passwd_format!(entries, options.validation, &options.defaults, debug));
+                    }
+                }
+            };
+            listed_entries(&database, passwd_vec, hidden, listing, debug)
+        })
+    }
+
+    /** Run the get_changes_since command for passwd with the token saved by the last refresh */
+    fn run_changes_since(config_deser: serde_json::Value, token: String, debug: bool) -> Response<index::Changes<Passwd>> {
+        let database = "passwd".to_string();
+        run_changes_since!(config_deser, database, token, parse_passwd_json, debug)
+    }
+}
+
+//////////////////
+// Shadow hooks //
+//////////////////
+
+struct ShimShadow;
+shadow_hooks!(shim, ShimShadow);
+
+impl ShadowHooks for ShimShadow {
+    fn get_all_entries() -> Response<Vec<Shadow>> {
+        enumerate_entries("shadow", Self::all_entries)
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Shadow> {
+        match Self::entry_by_name(name) {
+            Response::Success((shadow, _)) => Response::Success(shadow),
+            response => failure(response),
+        }
+    }
+}
+
+impl PagedHooks<Shadow> for ShimShadow {
+    fn get_page(position: Position) -> Response<Page<Shadow>> {
+        get_page("shadow", position, Self::run_page, Self::all_entries)
+    }
+}
+
+impl ShimShadow {
+    /** Look up a shadow entry by name, with whether it is hidden from enumeration */
+    fn entry_by_name(name: String) -> Response<(Shadow, bool)> {
+        validate_config!(config_deser, debug);
+        let database = "shadow".to_string();
+        let function = "get_entry_by_name".to_string();
+        let code = ("<$name>".to_string(), name.clone());
+        read_index!(config_deser, database, function, index::Key::Name(&name), parse_shadow_format, hidden, debug);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Shadow| x.name == name, hidden, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                validate_response!(output, option, debug);
+                parse_options!(config_deser, database, function, options, debug);
+                match option {
+                    Some(deser) => {
+                        for record in deser.records() {
+                            let (shadow_entry, record) = valid_record!(record, debug);
+                            if code.1 != shadow_entry {
+                                debug_print!(
+                                    format!(
+                                        "Requested name: {} does not match returned name: {}",
+                                        code.1,
+                                        shadow_entry.to_string()
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            parse_shadow_json!(shadow_entry, record, shadow, options.validation, &options.defaults, debug);
+                            return Response::Success((shadow, is_hidden(&record)));
+                        }
+                    }
+                    _ => {
+                        for line in colon_lines(output) {
+                            // Allowing 7 instead of 8 since the last field ('reserved') is truly optional
+                            let fields = split_colons(line, 9, options.escaped_colons);
+                            if fields.len() < 8 {
+                                debug_print!(
+                                    format!(
+                                        "Returned shadow data: {} does not match expected unix form",
+                                        line
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            let mut entries = fields.iter().map(|x| x.as_str());
+                            return Response::Success((parse_shadow_format!(entries, options.validation, &options.defaults, debug), false));
+                        }
+                    }
+                };
+                debug_print!(format!("Name: {} not found in shadow", name), debug);
+                Response::NotFound
+            })
+        })
+    }
+
+    /** Get every shadow entry, from the index file if configured
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Shadow>> {
+        validate_config!(config_deser, debug);
+        let database = "shadow".to_string();
+        let function = "get_all_entries".to_string();
+        // Read the entries from the index file instead of running a command, if configured
+        read_index!(config_deser, database, function, parse_shadow_format, listing, debug);
+        match Self::run_entries(config_deser, listing, debug) {
+            Response::Success((entries, _)) => Response::Success(entries),
+            response => failure(response),
+        }
+    }
+
+    /** Run the get_all_entries command for one page of shadow, if it is paged */
+    fn run_page(
+        config_deser: serde_json::Value,
+        position: Position,
+        limit: usize,
+        listing: bool,
+        debug: bool,
+    ) -> Response<Page<Shadow>> {
+        let database = "shadow".to_string();
+        run_page!(config_deser, database, position, limit, parse_shadow_json, parse_shadow_format, 7, 9, listing, debug)
+    }
+
+    /** Run the get_all_entries command for shadow and parse the output, with the positions of hidden entries
+    - This is used to build index files, so it never reads from the index */
+    fn run_all_entries(config_deser: serde_json::Value, debug: bool) -> Response<Entries<Shadow>> {
+        Self::run_entries(config_deser, false, debug)
+    }
+
+    /** Run the get_all_entries command for shadow and parse the output, with the positions of hidden entries
+    - When listing entries for enumeration, hidden entries are left out, along with their positions */
+    fn run_entries(config_deser: serde_json::Value, listing: bool, debug: bool) -> Response<Entries<Shadow>> {
+        let database = "shadow".to_string();
+        // Look up each name from get_all_names instead, if configured
+        if uses_all_names(&config_deser, &database) {
+            return expand_all_names(config_deser, &database, listing, debug, Self::entry_by_name);
+        }
+        // Combine every page, if get_all_entries is paged
+        match page_size(&config_deser, &database, debug) {
+            Ok(Some(limit)) => {
+                return paged::collect_pages(|position| Self::run_page(config_deser.clone(), position, limit, listing, debug))
+            }
+            Ok(None) => {}
+            Err(()) => return Response::Unavail,
+        }
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        // JSON Lines are parsed as they are read, unless the output is needed whole
+        stream_all_entries!(config_deser, database, function, code, parse_shadow_json, listing, streamed, debug);
+        let run = || {
+            if let Some(output) = streamed {
+                return Response::Success(output);
+            }
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            validate_response!(output, option, debug);
+            parse_options!(config_deser, database, function, options, debug);
+            let mut shadow_vec: Vec<Shadow> = Vec::new();
+            let mut hidden: HashSet<usize> = HashSet::new();
+            match option {
+                Some(deser) => {
+                    for record in deser.records() {
+                        let (shadow_entry, record) = valid_record!(record, debug);
+                        if is_hidden(&record) {
+                            hidden.insert(shadow_vec.len());
+                        }
+                        parse_shadow_json!(shadow_entry, record, shadow, options.validation, &options.defaults, debug);
+                        shadow_vec.push(shadow);
+                    }
+                }
+                _ => {
+                    for line in colon_lines(output) {
+                        // Allowing 7 instead of 8 since the last field ('reserved') is truly optional
+                        let fields = split_colons(line, 9, options.escaped_colons);
+                        if fields.len() < 8 {
+                            debug_print!(
+                                format!(
+                                    "Returned shadow data: {} does not match expected unix form",
+                                    line
+                                ),
+                                debug
+                            );
+                            return Response::TryAgain;
+                        }
+                        let mut entries = fields.iter().map(|x| x.as_str());
+                        shadow_vec.push(parse_shadow_format!(entries, options.validation, &options.defaults, debug));
+                    }
+                }
+            };
+            listed_entries(&database, shadow_vec, hidden, listing, debug)
+        })
+    }
+
+    /** Run the get_changes_since command for shadow with the token saved by the last refresh */
+    fn run_changes_since(config_deser: serde_json::Value, token: String, debug: bool) -> Response<index::Changes<Shadow>> {
+        let database = "shadow".to_string();
+        run_changes_since!(config_deser, database, token, parse_shadow_json, debug)
+    }
+}
+
+///////////////////
+// GShadow hooks //
+///////////////////
+
+struct ShimGShadow;
+gshadow_hooks!(shim, ShimGShadow);
+
+impl GShadowHooks for ShimGShadow {
+    fn get_all_entries() -> Response<Vec<GShadow>> {
+        enumerate_entries("gshadow", Self::all_entries)
+    }
+
+    fn get_entry_by_name(name: String) -> Response<GShadow> {
+        match Self::entry_by_name(name) {
+            Response::Success((gshadow, _)) => Response::Success(gshadow),
+            response => failure(response),
+        }
+    }
+}
+
+impl ShimGShadow {
+    /** Look up a gshadow entry by name, with whether it is hidden from enumeration */
+    fn entry_by_name(name: String) -> Response<(GShadow, bool)> {
+        validate_config!(config_deser, debug);
+        let database = "gshadow".to_string();
+        let function = "get_entry_by_name".to_string();
+        let code = ("<$name>".to_string(), name.clone());
+        read_index!(config_deser, database, function, index::Key::Name(&name), parse_gshadow_format, hidden, debug);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &GShadow| x.name == name, hidden, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                validate_response!(output, option, debug);
+                parse_options!(config_deser, database, function, options, debug);
+                match option {
+                    Some(deser) => {
+                        if let Some(record) = deser.records().next() {
+                            let (gshadow_entry, record) = valid_record!(record, debug);
+                            if code.1 != *gshadow_entry {
+                                debug_print!(
+                                    format!(
+                                        "Requested name: {} does not match returned name: {}",
+                                        code.1,
+                                        gshadow_entry
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            parse_gshadow_json!(gshadow_entry, record, gshadow, options.validation, &options.defaults, debug);
+                            return Response::Success((gshadow, is_hidden(&record)));
+                        }
+                    }
+                    _ => {
+                        if let Some(line) = colon_lines(output).next() {
+                            let fields = split_colons(line, 4, options.escaped_colons);
+                            if fields.len() < 4 {
+                                debug_print!(
+                                    format!(
+                                        "Returned gshadow data: {} does not match expected unix form",
+                                        line
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            let mut entries = fields.iter().map(|x| x.as_str());
+                            return Response::Success((parse_gshadow_format!(entries, options.validation, &options.defaults, debug), false));
+                        }
+                    }
+                };
+                debug_print!(format!("Name: {} not found in gshadow", name), debug);
+                Response::NotFound
+            })
+        })
+    }
+
+    /** Get every gshadow entry, from the index file if configured
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<GShadow>> {
+        validate_config!(config_deser, debug);
+        let database = "gshadow".to_string();
+        let function = "get_all_entries".to_string();
+        read_index!(config_deser, database, function, parse_gshadow_format, listing, debug);
+        match Self::run_entries(config_deser, listing, debug) {
+            Response::Success((entries, _)) => Response::Success(entries),
+            response => failure(response),
+        }
+    }
+
+    /** Run the get_all_entries command for gshadow and parse the output, with the positions of hidden entries
+    - This is used to build index files, so it never reads from the index */
+    fn run_all_entries(config_deser: serde_json::Value, debug: bool) -> Response<Entries<GShadow>> {
+        Self::run_entries(config_deser, false, debug)
+    }
+
+    /** Run the get_all_entries command for gshadow and parse the output, with the positions of hidden entries
+    - When listing entries for enumeration, hidden entries are left out, along with their positions */
+    fn run_entries(config_deser: serde_json::Value, listing: bool, debug: bool) -> Response<Entries<GShadow>> {
+        let database = "gshadow".to_string();
+        // Look up each name from get_all_names instead, if configured
+        if uses_all_names(&config_deser, &database) {
+            return expand_all_names(config_deser, &database, listing, debug, Self::entry_by_name);
+        }
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        // JSON Lines are parsed as they are read, unless the output is needed whole
+        stream_all_entries!(config_deser, database, function, code, parse_gshadow_json, listing, streamed, debug);
+        let run = || {
+            if let Some(output) = streamed {
+                return Response::Success(output);
+            }
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            validate_response!(output, option, debug);
+            parse_options!(config_deser, database, function, options, debug);
+            let mut gshadow_vec: Vec<GShadow> = Vec::new();
+            let mut hidden: HashSet<usize> = HashSet::new();
+            match option {
+                Some(deser) => {
+                    for record in deser.records() {
+                        let (gshadow_entry, record) = valid_record!(record, debug);
+                        if is_hidden(&record) {
+                            hidden.insert(gshadow_vec.len());
+                        }
+                        parse_gshadow_json!(gshadow_entry, record, gshadow, options.validation, &options.defaults, debug);
+                        gshadow_vec.push(gshadow);
+                    }
+                }
+                _ => {
+                    for line in colon_lines(output) {
+                        let fields = split_colons(line, 4, options.escaped_colons);
+                        if fields.len() < 4 {
+                            debug_print!(
+                                format!(
+                                    "Returned gshadow data: {} does not match expected unix form",
+                                    line
+                                ),
+                                debug
+                            );
+                            return Response::TryAgain;
+                        }
+                        let mut entries = fields.iter().map(|x| x.as_str());
+                        gshadow_vec.push(parse_gshadow_format!(entries, options.validation, &options.defaults, debug));
+                    }
+                }
+            };
+            listed_entries(&database, gshadow_vec, hidden, listing, debug)
+        })
+    }
+
+    /** Run the get_changes_since command for gshadow with the token saved by the last refresh */
+    fn run_changes_since(config_deser: serde_json::Value, token: String, debug: bool) -> Response<index::Changes<GShadow>> {
+        let database = "gshadow".to_string();
+        run_changes_since!(config_deser, database, token, parse_gshadow_json, debug)
+    }
+}
+
+/////////////////
+// Hosts hooks //
+/////////////////
+
+struct ShimHost;
+libnss_host_hooks!(shim, ShimHost);
+
+impl HostHooks for ShimHost {
+    fn get_all_entries() -> Response<Vec<Host>> {
+        enumerate_entries("hosts", Self::all_entries)
+    }
+
+    fn get_host_by_name(name: &str, family: AddressFamily) -> Response<Host> {
+        validate_config!(config_deser, debug);
+        let database = "hosts".to_string();
+        let function = "get_entry_by_name".to_string();
+        let name = name.to_string();
+        let family = match family {
+            AddressFamily::IPv4 => "ipv4",
+            AddressFamily::IPv6 => "ipv6",
+            AddressFamily::Unspecified => "",
+        };
+        // Set the codes for name and address family
+        let code = ("<$name>".to_string(), name.clone());
+        let family_code = ("<$family>".to_string(), family.to_string());
+        // Results differ by address family, so it is cached separately
+        let query = format!("{}/{}", name, family);
+        cache::with_negative_cache(&config_deser, &database, &function, &query, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Host| host_matches(x, &name, family), debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                let (command, env_vars) = replace_code(command, env_vars, &family_code);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &query, debug, run, |output| {
+                parse_hosts!(output, hosts, debug);
+                if !hosts.iter().any(|x| host_matches(x, &name, "")) {
+                    debug_print!(
+                        format!("Requested name: {} does not match returned hosts data: {}", name, output),
+                        debug
+                    );
+                    return Response::TryAgain;
+                }
+                match hosts.into_iter().find(|x| host_matches(x, &name, family)) {
+                    Some(host) => Response::Success(host),
+                    None => {
+                        debug_print!(format!("No {} addresses found for host: {}", family, name), debug);
+                        Response::NotFound
+                    }
+                }
+            })
+        })
+    }
+
+    fn get_host_by_addr(addr: IpAddr) -> Response<Host> {
+        validate_config!(config_deser, debug);
+        let database = "hosts".to_string();
+        let function = "get_entry_by_addr".to_string();
+        let family = match addr {
+            IpAddr::V4(_) => "ipv4",
+            IpAddr::V6(_) => "ipv6",
+        };
+        // Set the codes for address and address family
+        let code = ("<$addr>".to_string(), addr.to_string());
+        let family_code = ("<$family>".to_string(), family.to_string());
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Host| host_has_addr(x, &addr), debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                let (command, env_vars) = replace_code(command, env_vars, &family_code);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_hosts!(output, hosts, debug);
+                match hosts.into_iter().find(|x| host_has_addr(x, &addr)) {
+                    Some(host) => Response::Success(host),
+                    None => {
+                        debug_print!(
+                            format!("Requested address: {} does not match returned hosts data: {}", addr, output),
+                            debug
+                        );
+                        Response::TryAgain
+                    }
+                }
+            })
+        })
+    }
+}
+
+impl ShimHost {
+    /** Run the get_all_entries command for hosts and parse the output
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Host>> {
+        validate_config!(config_deser, debug);
+        let database = "hosts".to_string();
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        let run = || {
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            parse_hosts!(output, hosts, hidden, debug);
+            if !hosts.is_empty() {
+                if listing {
+                    return Response::Success(hosts.into_iter().filter(|x| !hidden.contains(&x.name)).collect());
+                }
+                return Response::Success(hosts);
+            }
+            debug_print!("Returned hosts data is invalid".to_string(), debug);
+            Response::TryAgain
+        })
+    }
+}
+
+////////////////////
+// Services hooks //
+////////////////////
+
+struct ShimService;
+service_hooks!(shim, ShimService);
+
+impl ServiceHooks for ShimService {
+    fn get_all_entries() -> Response<Vec<Service>> {
+        enumerate_entries("services", Self::all_entries)
+    }
+
+    fn get_entry_by_name(name: String, proto: Option<String>) -> Response<Service> {
+        validate_config!(config_deser, debug);
+        let database = "services".to_string();
+        let function = "get_entry_by_name".to_string();
+        // Set the codes for name and protocol, which is blank if any protocol can match
+        let proto = proto.unwrap_or_default();
+        let code = ("<$name>".to_string(), name.clone());
+        let proto_code = ("<$proto>".to_string(), proto.clone());
+        // Results differ by protocol, so it is cached separately
+        let query = format!("{}/{}", name, proto);
+        let matches = |x: &Service| has_name(&x.name, &x.aliases, &name) && (proto.is_empty() || x.proto == proto);
+        cache::with_negative_cache(&config_deser, &database, &function, &query, debug, || {
+            derive_from_all!(config_deser, database, function, matches, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                let (command, env_vars) = replace_code(command, env_vars, &proto_code);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &query, debug, run, |output| {
+                parse_whitespace_entries!(output, service_vec, parse_service_json, parse_service_format, debug);
+                if !service_vec.iter().any(|x| has_name(&x.name, &x.aliases, &name)) {
+                    debug_print!(
+                        format!("Requested name: {} does not match returned services data: {}", name, output),
+                        debug
+                    );
+                    return Response::TryAgain;
+                }
+                match service_vec.into_iter().find(matches) {
+                    Some(service) => Response::Success(service),
+                    None => {
+                        debug_print!(format!("Name: {} not found in services for protocol: {}", name, proto), debug);
+                        Response::NotFound
+                    }
+                }
+            })
+        })
+    }
+
+    fn get_entry_by_port(port: u16, proto: Option<String>) -> Response<Service> {
+        validate_config!(config_deser, debug);
+        let database = "services".to_string();
+        let function = "get_entry_by_port".to_string();
+        let proto = proto.unwrap_or_default();
+        let code = ("<$port>".to_string(), port.to_string());
+        let proto_code = ("<$proto>".to_string(), proto.clone());
+        let query = format!("{}/{}", port, proto);
+        let matches = |x: &Service| x.port == port && (proto.is_empty() || x.proto == proto);
+        cache::with_negative_cache(&config_deser, &database, &function, &query, debug, || {
+            derive_from_all!(config_deser, database, function, matches, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                let (command, env_vars) = replace_code(command, env_vars, &proto_code);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &query, debug, run, |output| {
+                parse_whitespace_entries!(output, service_vec, parse_service_json, parse_service_format, debug);
+                if !service_vec.iter().any(|x| x.port == port) {
+                    debug_print!(
+                        format!("Requested port: {} does not match returned services data: {}", port, output),
+                        debug
+                    );
+                    return Response::TryAgain;
+                }
+                match service_vec.into_iter().find(matches) {
+                    Some(service) => Response::Success(service),
+                    None => {
+                        debug_print!(format!("Port: {} not found in services for protocol: {}", port, proto), debug);
+                        Response::NotFound
+                    }
+                }
+            })
+        })
+    }
+}
+
+impl ShimService {
+    /** Run the get_all_entries command for services and parse the output
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Service>> {
+        validate_config!(config_deser, debug);
+        let database = "services".to_string();
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        let run = || {
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            parse_whitespace_entries!(output, service_vec, hidden, parse_service_json, parse_service_format, debug);
+            if !service_vec.is_empty() {
+                if listing {
+                    service_vec = without_hidden(service_vec, &hidden);
+                }
+                return Response::Success(service_vec);
+            }
+            debug_print!("Returned services data is invalid".to_string(), debug);
+            Response::TryAgain
+        })
+    }
+}
+
+/////////////////////
+// Protocols hooks //
+/////////////////////
+
+struct ShimProtocol;
+protocol_hooks!(shim, ShimProtocol);
+
+impl ProtocolHooks for ShimProtocol {
+    fn get_all_entries() -> Response<Vec<Protocol>> {
+        enumerate_entries("protocols", Self::all_entries)
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Protocol> {
+        validate_config!(config_deser, debug);
+        let database = "protocols".to_string();
+        let function = "get_entry_by_name".to_string();
+        let code = ("<$name>".to_string(), name.clone());
+        let matches = |x: &Protocol| has_name(&x.name, &x.aliases, &name);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, matches, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_whitespace_entries!(output, protocol_vec, parse_protocol_json, parse_protocol_format, debug);
+                match protocol_vec.into_iter().find(matches) {
+                    Some(protocol) => Response::Success(protocol),
+                    None => {
+                        debug_print!(
+                            format!("Requested name: {} does not match returned protocols data: {}", name, output),
+                            debug
+                        );
+                        Response::TryAgain
+                    }
+                }
+            })
+        })
+    }
+
+    fn get_entry_by_number(number: i32) -> Response<Protocol> {
+        validate_config!(config_deser, debug);
+        let database = "protocols".to_string();
+        let function = "get_entry_by_number".to_string();
+        let code = ("<$number>".to_string(), number.to_string());
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Protocol| x.number == number, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_whitespace_entries!(output, protocol_vec, parse_protocol_json, parse_protocol_format, debug);
+                match protocol_vec.into_iter().find(|x| x.number == number) {
+                    Some(protocol) => Response::Success(protocol),
+                    None => {
+                        debug_print!(
+                            format!("Requested number: {} does not match returned protocols data: {}", number, output),
+                            debug
+                        );
+                        Response::TryAgain
+                    }
+                }
+            })
+        })
+    }
+}
+
+impl ShimProtocol {
+    /** Run the get_all_entries command for protocols and parse the output
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Protocol>> {
+        validate_config!(config_deser, debug);
+        let database = "protocols".to_string();
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        let run = || {
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            parse_whitespace_entries!(output, protocol_vec, hidden, parse_protocol_json, parse_protocol_format, debug);
+            if !protocol_vec.is_empty() {
+                if listing {
+                    protocol_vec = without_hidden(protocol_vec, &hidden);
+                }
+                return Response::Success(protocol_vec);
+            }
+            debug_print!("Returned protocols data is invalid".to_string(), debug);
+            Response::TryAgain
+        })
     }
 }
 
-//////////////////
-// Passwd hooks //
-//////////////////
+///////////////
+// RPC hooks //
+///////////////
 
-struct ShimPasswd;
-libnss_passwd_hooks!(shim, ShimPasswd);
+struct ShimRpc;
+rpc_hooks!(shim, ShimRpc);
 
-impl PasswdHooks for ShimPasswd {
-    fn get_all_entries() -> Response<Vec<Passwd>> {
+impl RpcHooks for ShimRpc {
+    fn get_all_entries() -> Response<Vec<Rpc>> {
+        enumerate_entries("rpc", Self::all_entries)
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Rpc> {
         validate_config!(config_deser, debug);
-        let database = "passwd".to_string();
-        let function = "get_all_entries".to_string();
-        parse_config!(config_deser, database, function, command, env_vars, dir, debug);
-        let code = ("".to_string(), "".to_string());
-        run_command_capture_output!(command, env_vars, dir, code, output, debug);
-        validate_response!(output, option, debug);
-        let mut passwd_vec: Vec<Passwd> = Vec::new();
-        match option {
-            Some(deser) => {
-                for passwd_entry in deser.as_object().unwrap().keys() {
-                    parse_passwd_json!(passwd_entry, deser, passwd, debug);
-                    passwd_vec.push(passwd);
+        let database = "rpc".to_string();
+        let function = "get_entry_by_name".to_string();
+        let code = ("<$name>".to_string(), name.clone());
+        let matches = |x: &Rpc| has_name(&x.name, &x.aliases, &name);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, matches, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_whitespace_entries!(output, rpc_vec, parse_rpc_json, parse_rpc_format, debug);
+                match rpc_vec.into_iter().find(matches) {
+                    Some(rpc) => Response::Success(rpc),
+                    None => {
+                        debug_print!(
+                            format!("Requested name: {} does not match returned rpc data: {}", name, output),
+                            debug
+                        );
+                        Response::TryAgain
+                    }
                 }
-            }
-            _ => {
-                for line in output.trim().lines() {
-                    if line.matches(":").count() < 6 {
+            })
+        })
+    }
+
+    fn get_entry_by_number(number: i32) -> Response<Rpc> {
+        validate_config!(config_deser, debug);
+        let database = "rpc".to_string();
+        let function = "get_entry_by_number".to_string();
+        let code = ("<$number>".to_string(), number.to_string());
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Rpc| x.number == number, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_whitespace_entries!(output, rpc_vec, parse_rpc_json, parse_rpc_format, debug);
+                match rpc_vec.into_iter().find(|x| x.number == number) {
+                    Some(rpc) => Response::Success(rpc),
+                    None => {
                         debug_print!(
-                            format!(
-                                "Returned passwd data: {} does not match expected unix form",
-                                line
-                            ),
+                            format!("Requested number: {} does not match returned rpc data: {}", number, output),
                             debug
                         );
-                        return Response::TryAgain;
+                        Response::TryAgain
                     }
-                    let mut entries = line.trim().splitn(7, ':');
-                    passwd_vec.push(parse_passwd_format!(entries, debug));
                 }
-            }
+            })
+        })
+    }
+}
+
+impl ShimRpc {
+    /** Run the get_all_entries command for rpc and parse the output
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Rpc>> {
+        validate_config!(config_deser, debug);
+        let database = "rpc".to_string();
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        let run = || {
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
         };
-        if passwd_vec.len() > 0 {
-            return Response::Success(passwd_vec);
-        }
-        debug_print!("Returned passwd data is invalid".to_string(), debug);
-        return Response::TryAgain;
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            parse_whitespace_entries!(output, rpc_vec, hidden, parse_rpc_json, parse_rpc_format, debug);
+            if !rpc_vec.is_empty() {
+                if listing {
+                    rpc_vec = without_hidden(rpc_vec, &hidden);
+                }
+                return Response::Success(rpc_vec);
+            }
+            debug_print!("Returned rpc data is invalid".to_string(), debug);
+            Response::TryAgain
+        })
     }
+}
 
-    fn get_entry_by_uid(uid: libc::uid_t) -> Response<Passwd> {
+////////////////////
+// Networks hooks //
+////////////////////
+
+struct ShimNetwork;
+network_hooks!(shim, ShimNetwork);
+
+impl NetworkHooks for ShimNetwork {
+    fn get_all_entries() -> Response<Vec<Network>> {
+        enumerate_entries("networks", Self::all_entries)
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Network> {
         validate_config!(config_deser, debug);
-        let database = "passwd".to_string();
-        let function = "get_entry_by_uid".to_string();
-        parse_config!(config_deser, database, function, command, env_vars, dir, debug);
-        let code = ("<$uid>".to_string(), uid.to_string());
-        run_command_capture_output!(command, env_vars, dir, code, output, debug);
-        validate_response!(output, option, debug);
-        match option {
-            Some(deser) => {
-                for passwd_entry in deser.as_object().unwrap().keys() {
-                    if uid != match deser[passwd_entry]["uid"].as_u64() {
-                        Some(x) => x,
-                        _ => {
-                            debug_print!(
-                                    format!(
-                                        "Returned passwd data: {} does not contain a valid uid",
-                                        deser
-                                    ),
-                                    debug
-                                );
-                            return Response::TryAgain;
-                        }
-                    } as u32
-                    {
+        let database = "networks".to_string();
+        let function = "get_entry_by_name".to_string();
+        let code = ("<$name>".to_string(), name.clone());
+        let matches = |x: &Network| has_name(&x.name, &x.aliases, &name);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, matches, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_whitespace_entries!(output, network_vec, parse_network_json, parse_network_format, debug);
+                match network_vec.into_iter().find(matches) {
+                    Some(network) => Response::Success(network),
+                    None => {
                         debug_print!(
-                            format!(
-                                "Returned passwd data: {} does not contain a matching uid: {}",
-                                deser,
-                                uid
-                            ),
+                            format!("Requested name: {} does not match returned networks data: {}", name, output),
                             debug
                         );
-                        return Response::TryAgain;
+                        Response::TryAgain
                     }
-                    parse_passwd_json!(passwd_entry, deser, passwd, debug);
-                    return Response::Success(passwd);
                 }
-            }
-            _ => {
-                for line in output.trim().lines() {
-                    if line.matches(":").count() < 6 {
+            })
+        })
+    }
+
+    fn get_entry_by_addr(net: u32) -> Response<Network> {
+        validate_config!(config_deser, debug);
+        let database = "networks".to_string();
+        let function = "get_entry_by_addr".to_string();
+        // Set the code for the network in the same dotted form as /etc/networks
+        let code = ("<$addr>".to_string(), networks::network_string(net));
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Network| x.net == net, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_whitespace_entries!(output, network_vec, parse_network_json, parse_network_format, debug);
+                match network_vec.into_iter().find(|x| x.net == net) {
+                    Some(network) => Response::Success(network),
+                    None => {
                         debug_print!(
-                            format!(
-                                "Returned passwd data: {} does not match expected unix form",
-                                line
-                            ),
+                            format!("Requested network: {} does not match returned networks data: {}", code.1, output),
                             debug
                         );
-                        return Response::TryAgain;
+                        Response::TryAgain
                     }
-                    let mut entries = line.trim().splitn(7, ':');
-                    return Response::Success(parse_passwd_format!(entries, debug));
                 }
-            }
+            })
+        })
+    }
+}
+
+impl ShimNetwork {
+    /** Run the get_all_entries command for networks and parse the output
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Network>> {
+        validate_config!(config_deser, debug);
+        let database = "networks".to_string();
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        let run = || {
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
         };
-        debug_print!(format!("uid: {} not found in passwd", uid), debug);
-        return Response::NotFound;
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            parse_whitespace_entries!(output, network_vec, hidden, parse_network_json, parse_network_format, debug);
+            if !network_vec.is_empty() {
+                if listing {
+                    network_vec = without_hidden(network_vec, &hidden);
+                }
+                return Response::Success(network_vec);
+            }
+            debug_print!("Returned networks data is invalid".to_string(), debug);
+            Response::TryAgain
+        })
     }
+}
 
-    fn get_entry_by_name(name: String) -> Response<Passwd> {
+//////////////////
+// Ethers hooks //
+//////////////////
+
+struct ShimEther;
+ether_hooks!(shim, ShimEther);
+
+impl EtherHooks for ShimEther {
+    fn get_all_entries() -> Response<Vec<Ether>> {
+        enumerate_entries("ethers", Self::all_entries)
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Ether> {
         validate_config!(config_deser, debug);
-        let database = "passwd".to_string();
+        let database = "ethers".to_string();
         let function = "get_entry_by_name".to_string();
-        parse_config!(config_deser, database, function, command, env_vars, dir, debug);
         let code = ("<$name>".to_string(), name.clone());
-        run_command_capture_output!(command, env_vars, dir, code, output, debug);
-        validate_response!(output, option, debug);
-        match option {
-            Some(deser) => {
-                for passwd_entry in deser.as_object().unwrap().keys() {
-                    if code.1 != passwd_entry.to_string() {
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Ether| x.name == name, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_whitespace_entries!(output, ether_vec, parse_ether_json, parse_ether_format, debug);
+                match ether_vec.into_iter().find(|x| x.name == name) {
+                    Some(ether) => Response::Success(ether),
+                    None => {
                         debug_print!(
-                            format!(
-                                "Requested name: {} does not match returned name: {}",
-                                code.1,
-                                passwd_entry.to_string()
-                            ),
+                            format!("Requested name: {} does not match returned ethers data: {}", name, output),
                             debug
                         );
-                        return Response::TryAgain;
+                        Response::TryAgain
                     }
-                    parse_passwd_json!(passwd_entry, deser, passwd, debug);
-                    return Response::Success(passwd);
                 }
-            }
-            _ => {
-                for line in output.trim().lines() {
-                    if line.matches(":").count() < 6 {
+            })
+        })
+    }
+
+    fn get_entry_by_mac(mac: [u8; 6]) -> Response<Ether> {
+        validate_config!(config_deser, debug);
+        let database = "ethers".to_string();
+        let function = "get_entry_by_mac".to_string();
+        let code = ("<$mac>".to_string(), ethers::mac_string(&mac));
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, |x: &Ether| x.mac == mac, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_whitespace_entries!(output, ether_vec, parse_ether_json, parse_ether_format, debug);
+                match ether_vec.into_iter().find(|x| x.mac == mac) {
+                    Some(ether) => Response::Success(ether),
+                    None => {
                         debug_print!(
-                            format!(
-                                "Returned passwd data: {} does not match expected unix form",
-                                line
-                            ),
+                            format!("Requested MAC address: {} does not match returned ethers data: {}", code.1, output),
                             debug
                         );
-                        return Response::TryAgain;
+                        Response::TryAgain
                     }
-                    let mut entries = line.trim().splitn(7, ':');
-                    return Response::Success(parse_passwd_format!(entries, debug));
                 }
-            }
+            })
+        })
+    }
+}
+
+impl ShimEther {
+    /** Run the get_all_entries command for ethers and parse the output
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Ether>> {
+        validate_config!(config_deser, debug);
+        let database = "ethers".to_string();
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        let run = || {
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
         };
-        debug_print!(format!("Name: {} not found in passwd", name), debug);
-        return Response::NotFound;
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            parse_whitespace_entries!(output, ether_vec, hidden, parse_ether_json, parse_ether_format, debug);
+            if !ether_vec.is_empty() {
+                if listing {
+                    ether_vec = without_hidden(ether_vec, &hidden);
+                }
+                return Response::Success(ether_vec);
+            }
+            debug_print!("Returned ethers data is invalid".to_string(), debug);
+            Response::TryAgain
+        })
     }
 }
 
-//////////////////
-// Shadow hooks //
-//////////////////
+////////////////////
+// Netgroup hooks //
+////////////////////
 
-struct ShimShadow;
-libnss_shadow_hooks!(shim, ShimShadow);
+struct ShimNetgroup;
+netgroup_hooks!(shim, ShimNetgroup);
 
-impl ShadowHooks for ShimShadow {
-    fn get_all_entries() -> Response<Vec<Shadow>> {
+impl NetgroupHooks for ShimNetgroup {
+    fn get_entry_by_name(name: String) -> Response<Vec<NetgroupEntry>> {
         validate_config!(config_deser, debug);
-        let database = "shadow".to_string();
-        let function = "get_all_entries".to_string();
-        parse_config!(config_deser, database, function, command, env_vars, dir, debug);
-        let code = ("".to_string(), "".to_string());
-        run_command_capture_output!(command, env_vars, dir, code, output, debug);
-        validate_response!(output, option, debug);
-        let mut shadow_vec: Vec<Shadow> = Vec::new();
-        match option {
-            Some(deser) => {
-                for shadow_entry in deser.as_object().unwrap().keys() {
-                    parse_shadow_json!(shadow_entry, deser, shadow, debug);
-                    shadow_vec.push(shadow);
+        let database = "netgroup".to_string();
+        let function = "get_entry_by_name".to_string();
+        let expand_nested = match find_setting(&config_deser, &database, &function, "expand_nested") {
+            None => false,
+            Some(x) => match x.as_bool() {
+                Some(x) => x,
+                None => {
+                    debug_print!("expand_nested in config.json must be a boolean", debug);
+                    return Response::Unavail;
+                }
+            },
+        };
+        if !expand_nested {
+            return Self::run_entry_by_name(config_deser, &name, debug);
+        }
+        // Expand nested netgroups in the shim, handing back to glibc only those which the shim does not know
+        let mut members = Vec::new();
+        let mut seen = HashSet::from([name.clone()]);
+        let mut pending = VecDeque::from([name.clone()]);
+        while let Some(group) = pending.pop_front() {
+            match Self::run_entry_by_name(config_deser.clone(), &group, debug) {
+                Response::Success(entries) => {
+                    for entry in entries {
+                        match entry {
+                            NetgroupEntry::Group(nested) => {
+                                if seen.insert(nested.clone()) {
+                                    pending.push_back(nested);
+                                }
+                            }
+                            triple => members.push(triple),
+                        }
+                    }
                 }
+                Response::NotFound if group != name => members.push(NetgroupEntry::Group(group)),
+                response => return response,
             }
-            _ => {
-                for line in output.trim().lines() {
-                    // Allowing 7 instead of 8 since the last field ('reserved') is truly optional
-                    if line.matches(":").count() < 7 {
+        }
+        Response::Success(members)
+    }
+}
+
+impl ShimNetgroup {
+    /** Run the get_entry_by_name command for a netgroup and parse the output, without expanding nested netgroups */
+    fn run_entry_by_name(config_deser: serde_json::Value, name: &str, debug: bool) -> Response<Vec<NetgroupEntry>> {
+        let database = "netgroup".to_string();
+        let function = "get_entry_by_name".to_string();
+        let code = ("<$name>".to_string(), name.to_string());
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                validate_response!(output, option, debug);
+                match option {
+                    Some(deser) => {
+                        if let Some(record) = deser.records().next() {
+                            let (netgroup_entry, record) = valid_record!(record, debug);
+                            if code.1 != *netgroup_entry {
+                                debug_print!(
+                                    format!(
+                                        "Requested name: {} does not match returned name: {}",
+                                        code.1,
+                                        netgroup_entry
+                                    ),
+                                    debug
+                                );
+                                return Response::TryAgain;
+                            }
+                            parse_netgroup_json!(netgroup_entry, record, members, debug);
+                            return Response::Success(members);
+                        }
+                    }
+                    _ => {
+                        for line in output.trim().lines() {
+                            let line = line.split('#').next().unwrap_or("").trim();
+                            if line.is_empty() {
+                                continue;
+                            }
+                            let (netgroup_entry, members) = parse_netgroup_format!(line, debug);
+                            if netgroup_entry == name {
+                                return Response::Success(members);
+                            }
+                        }
                         debug_print!(
-                            format!(
-                                "Returned shadow data: {} does not match expected unix form",
-                                line
-                            ),
+                            format!("Requested name: {} does not match returned netgroup data: {}", name, output),
                             debug
                         );
                         return Response::TryAgain;
                     }
-                    let mut entries = line.trim().splitn(9, ':');
-                    shadow_vec.push(parse_shadow_format!(entries, debug));
-                }
-            }
-        };
-        if shadow_vec.len() > 0 {
-            return Response::Success(shadow_vec);
-        }
-        debug_print!("Returned shadow data is invalid".to_string(), debug);
-        return Response::TryAgain;
+                };
+                debug_print!(format!("Name: {} not found in netgroup", name), debug);
+                Response::NotFound
+            })
+        })
+    }
+}
+
+///////////////////
+// Aliases hooks //
+///////////////////
+
+struct ShimAlias;
+alias_hooks!(shim, ShimAlias);
+
+impl AliasHooks for ShimAlias {
+    fn get_all_entries() -> Response<Vec<Alias>> {
+        enumerate_entries("aliases", Self::all_entries)
     }
 
-    fn get_entry_by_name(name: String) -> Response<Shadow> {
+    fn get_entry_by_name(name: String) -> Response<Alias> {
         validate_config!(config_deser, debug);
-        let database = "shadow".to_string();
+        let database = "aliases".to_string();
         let function = "get_entry_by_name".to_string();
-        parse_config!(config_deser, database, function, command, env_vars, dir, debug);
         let code = ("<$name>".to_string(), name.clone());
-        run_command_capture_output!(command, env_vars, dir, code, output, debug);
-        validate_response!(output, option, debug);
-        match option {
-            Some(deser) => {
-                for shadow_entry in deser.as_object().unwrap().keys() {
-                    if code.1 != shadow_entry.to_string() {
+        // Alias names are not case-sensitive, as in /etc/aliases
+        let matches = |x: &Alias| x.name.eq_ignore_ascii_case(&name);
+        cache::with_negative_cache(&config_deser, &database, &function, &code.1, debug, || {
+            derive_from_all!(config_deser, database, function, matches, debug);
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+                parse_aliases!(output, alias_vec, debug);
+                match alias_vec.into_iter().find(matches) {
+                    Some(alias) => Response::Success(alias),
+                    None => {
                         debug_print!(
-                            format!(
-                                "Requested name: {} does not match returned name: {}",
-                                code.1,
-                                shadow_entry.to_string()
-                            ),
+                            format!("Requested name: {} does not match returned aliases data: {}", name, output),
                             debug
                         );
-                        return Response::TryAgain;
+                        Response::TryAgain
                     }
-                    parse_shadow_json!(shadow_entry, deser, shadow, debug);
-                    return Response::Success(shadow);
                 }
+            })
+        })
+    }
+}
+
+impl ShimAlias {
+    /** Run the get_all_entries command for aliases and parse the output
+    - When listing entries for enumeration, hidden entries are left out */
+    fn all_entries(listing: bool) -> Response<Vec<Alias>> {
+        validate_config!(config_deser, debug);
+        let database = "aliases".to_string();
+        let function = "get_all_entries".to_string();
+        let code = ("".to_string(), "".to_string());
+        let run = || {
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            parse_aliases!(output, alias_vec, hidden, debug);
+            if !alias_vec.is_empty() {
+                if listing {
+                    alias_vec = without_hidden(alias_vec, &hidden);
+                }
+                return Response::Success(alias_vec);
             }
-            _ => {
-                for line in output.trim().lines() {
-                    // Allowing 7 instead of 8 since the last field ('reserved') is truly optional
-                    if line.matches(":").count() < 7 {
-                        debug_print!(
-                            format!(
-                                "Returned shadow data: {} does not match expected unix form",
-                                line
-                            ),
-                            debug
-                        );
-                        return Response::TryAgain;
+            debug_print!("Returned aliases data is invalid".to_string(), debug);
+            Response::TryAgain
+        })
+    }
+}
+
+/////////////////
+// Subid hooks //
+/////////////////
+
+struct ShimSubid;
+subid_hooks!(ShimSubid);
+
+impl SubidHooks for ShimSubid {
+    fn get_all_entries(kind: SubidKind) -> Response<Vec<SubidRange>> {
+        validate_config!(config_deser, debug);
+        let database = "subid".to_string();
+        let function = "get_all_entries".to_string();
+        let code = ("<$kind>".to_string(), kind.as_str().to_string());
+        let run = || {
+            parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+            run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+            Response::Success(output)
+        };
+        cache::with_disk_cache(&config_deser, &database, &function, &code.1, debug, run, |output| {
+            parse_subid_ranges!(output, ranges, debug);
+            Response::Success(ranges)
+        })
+    }
+
+    fn get_ranges_by_owner(owner: String, kind: SubidKind) -> Response<Vec<SubidRange>> {
+        validate_config!(config_deser, debug);
+        let database = "subid".to_string();
+        let function = "get_ranges_by_owner".to_string();
+        // Set the codes for the owner and the type of ranges
+        let code = ("<$name>".to_string(), owner.clone());
+        let kind_code = ("<$kind>".to_string(), kind.as_str().to_string());
+        // Results differ by type, so they are cached separately
+        let query = format!("{}/{}", owner, kind.as_str());
+        match should_derive(&config_deser, &database, &function, debug) {
+            Ok(true) => {
+                debug_print!(format!("Deriving {} {} from get_all_entries", database, function), debug);
+                return match Self::get_all_entries(kind) {
+                    Response::Success(ranges) => {
+                        let uid = subid::matching_uid(&ranges, &owner);
+                        Response::Success(ranges.into_iter().filter(|x| subid::owner_matches(x, &owner, uid)).collect())
                     }
-                    let mut entries = line.trim().splitn(9, ':');
-                    return Response::Success(parse_shadow_format!(entries, debug));
+                    response => failure(response),
+                };
+            }
+            Ok(false) => {}
+            Err(()) => return Response::Unavail,
+        }
+        cache::with_negative_cache(&config_deser, &database, &function, &query, debug, || {
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                let (command, env_vars) = replace_code(command, env_vars, &kind_code);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &query, debug, run, |output| {
+                parse_subid_ranges!(output, ranges, debug);
+                let uid = subid::matching_uid(&ranges, &owner);
+                if !ranges.iter().all(|x| subid::owner_matches(x, &owner, uid)) {
+                    debug_print!(
+                        format!("Requested owner: {} does not match returned subid data: {}", owner, output),
+                        debug
+                    );
+                    return Response::TryAgain;
                 }
+                Response::Success(ranges)
+            })
+        })
+    }
+
+    fn get_owners_by_id(id: u64, kind: SubidKind) -> Response<Vec<SubidRange>> {
+        validate_config!(config_deser, debug);
+        let database = "subid".to_string();
+        let function = "get_owners_by_id".to_string();
+        // Set the codes for the id and the type of ranges
+        let code = ("<$id>".to_string(), id.to_string());
+        let kind_code = ("<$kind>".to_string(), kind.as_str().to_string());
+        // Results differ by type, so they are cached separately
+        let query = format!("{}/{}", id, kind.as_str());
+        match should_derive(&config_deser, &database, &function, debug) {
+            Ok(true) => {
+                debug_print!(format!("Deriving {} {} from get_all_entries", database, function), debug);
+                return match Self::get_all_entries(kind) {
+                    Response::Success(ranges) => Response::Success(ranges.into_iter().filter(|x| x.contains(id)).collect()),
+                    response => failure(response),
+                };
+            }
+            Ok(false) => {}
+            Err(()) => return Response::Unavail,
+        }
+        cache::with_negative_cache(&config_deser, &database, &function, &query, debug, || {
+            let run = || {
+                parse_config!(config_deser, database, function, command, env_vars, dir, timeout, debug);
+                let (command, env_vars) = replace_code(command, env_vars, &kind_code);
+                run_command_capture_output!(command, env_vars, dir, timeout, code, output, debug);
+                Response::Success(output)
+            };
+            cache::with_disk_cache(&config_deser, &database, &function, &query, debug, run, |output| {
+                parse_subid_ranges!(output, ranges, debug);
+                // Ranges which do not contain the id are ignored, so commands can return every range of an owner
+                Response::Success(ranges.into_iter().filter(|x| x.contains(id)).collect())
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(line: &str, count: usize, escaped: bool) -> Vec<String> {
+        split_colons(line, count, escaped)
+    }
+
+    #[test]
+    fn splits_colons_without_escapes() {
+        assert_eq!(fields("alice:x:1000", 3, false), vec!["alice", "x", "1000"]);
+        // The last field keeps any remaining colons
+        assert_eq!(fields("alice:x:1000:a:b", 3, false), vec!["alice", "x", "1000:a:b"]);
+        assert_eq!(fields("a\\:b:x", 3, false), vec!["a\\", "b", "x"]);
+        assert_eq!(fields("", 3, false), vec![""]);
+    }
+
+    #[test]
+    fn splits_colons_with_escapes() {
+        assert_eq!(fields("a\\:b:x", 3, true), vec!["a:b", "x"]);
+        assert_eq!(fields("a\\x3ab:x", 3, true), vec!["a:b", "x"]);
+        assert_eq!(fields("a\\x3Ab:x", 3, true), vec!["a:b", "x"]);
+        assert_eq!(fields("a\\\\:b", 3, true), vec!["a\\", "b"]);
+        assert_eq!(fields("a\\\\x3a:b", 3, true), vec!["a\\x3a", "b"]);
+        // Colons in the last field are kept, and still decoded
+        assert_eq!(fields("a:b:c\\:d:e", 3, true), vec!["a", "b", "c:d:e"]);
+        // Invalid and non-ASCII hex escapes are left as they are, as is a trailing backslash
+        assert_eq!(fields("a\\xzz:b", 3, true), vec!["a\\xzz", "b"]);
+        assert_eq!(fields("a\\x3:b", 3, true), vec!["a\\x3", "b"]);
+        assert_eq!(fields("a\\xff:b", 3, true), vec!["a\\xff", "b"]);
+        assert_eq!(fields("a:b\\", 3, true), vec!["a", "b\\"]);
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let lines: Vec<&str> = colon_lines("# users\nalice:x\n\n  \n  bob:x  \n#carol:x").collect();
+        assert_eq!(lines, vec!["alice:x", "bob:x"]);
+    }
+
+    #[test]
+    fn reads_envelope_statuses() {
+        let status = |status: &str| envelope_status(&serde_json::json!({"status": status, "message": "m"}), false);
+        assert_eq!(status("success"), Some(Response::Success(())));
+        assert_eq!(status("notfound"), Some(Response::NotFound));
+        assert_eq!(status("unavail"), Some(Response::Unavail));
+        assert_eq!(status("tryagain"), Some(Response::TryAgain));
+        assert_eq!(status("unknown"), Some(Response::TryAgain));
+        // Objects keyed by name never have a string as an entry, so they are not envelopes
+        assert_eq!(envelope_status(&serde_json::json!({"status": {"gid": 1}}), false), None);
+        assert_eq!(envelope_status(&serde_json::json!({"alice": {"uid": 1}}), false), None);
+    }
+
+    #[test]
+    fn opens_envelopes() {
+        let entries = serde_json::json!({"alice": {"uid": 1}});
+        let envelope = serde_json::json!({"status": "success", "entries": entries.clone()});
+        assert_eq!(open_envelope(envelope, false), Ok(entries.clone()));
+        assert_eq!(open_envelope(entries.clone(), false), Ok(entries));
+        assert_eq!(open_envelope(serde_json::json!({"status": "success"}), false), Err(Response::NotFound));
+        assert_eq!(open_envelope(serde_json::json!({"status": "unavail"}), false), Err(Response::Unavail));
+    }
+
+    #[test]
+    fn numbers_invalid_json_lines() {
+        let output = "{\"name\": \"alice\", \"uid\": 1}\n\n{\"uid\": 2}\nnot json";
+        let output = JsonOutput::Lines(output);
+        let records: Vec<JsonRecord> = output.records().collect();
+        assert_eq!(records.len(), 3);
+        let (name, record) = records[0].as_ref().unwrap();
+        assert_eq!(name, "alice");
+        assert_eq!(record["uid"], 1);
+        // Blank lines are skipped, but still counted
+        assert!(records[1].as_ref().unwrap_err().starts_with("JSON line 3 is not a record with a name"));
+        assert!(records[2].as_ref().unwrap_err().starts_with("JSON line 4 is invalid"));
+    }
+
+    /** Run a shell script, and parse its output with stream_json_lines, giving each record as its name */
+    fn stream(script: &str, timeout: Option<Duration>) -> Response<StreamedOutput<String>> {
+        let lines = OutputLines::spawn(Command::new("sh").args(["-c", script]), timeout).unwrap();
+        stream_json_lines(lines, false, |name, _| Response::Success(name))
+    }
+
+    #[test]
+    fn streams_json_lines() {
+        let script = "echo; echo '{\"name\": \"alice\"}'; echo '{\"name\": \"bob\", \"hidden\": true}'; echo";
+        match stream(script, None) {
+            Response::Success(StreamedOutput::Records((names, hidden))) => {
+                assert_eq!(names, vec!["alice", "bob"]);
+                assert_eq!(hidden, HashSet::from([1]));
             }
+            _ => panic!("JSON Lines were not streamed"),
+        }
+        assert!(matches!(
+            stream("echo '{\"name\": \"alice\"}'; echo; echo 'not json'", None),
+            Response::TryAgain
+        ));
+    }
+
+    #[test]
+    fn reads_other_output_whole() {
+        let whole = |script: &str| match stream(script, None) {
+            Response::Success(StreamedOutput::Whole(output)) => output,
+            _ => panic!("output was not read whole"),
         };
-        debug_print!(format!("Name: {} not found in shadow", name), debug);
-        return Response::NotFound;
+        assert_eq!(whole("echo; echo 'alice:x:1'; echo 'bob:x:2'; echo"), "alice:x:1\nbob:x:2");
+        assert_eq!(whole("echo '{'; echo '\"alice\": {\"uid\": 1}'; echo '}'"), "{\n\"alice\": {\"uid\": 1}\n}");
+        assert_eq!(whole("echo '{\"alice\": {\"uid\": 1}}'"), "{\"alice\": {\"uid\": 1}}");
+        assert_eq!(whole("true"), "");
+    }
+
+    #[test]
+    fn stops_streaming_at_the_timeout() {
+        let script = "echo '{\"name\": \"alice\"}'; exec sleep 5";
+        let started = Instant::now();
+        assert!(matches!(stream(script, Some(Duration::from_millis(200))), Response::TryAgain));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    fn record(name: &str, hidden: bool) -> index::Record {
+        index::Record {
+            name: name.to_string(),
+            id: None,
+            hidden,
+            fields: vec![name.to_string(), "old".to_string()],
+        }
+    }
+
+    #[test]
+    fn applies_changes_to_records() {
+        let mut records = vec![record("alice", false), record("bob", true), record("carol", false)];
+        let upserts = vec![("bob", "new"), ("dave", "new"), ("alice", "new")];
+        let deletions = vec!["carol".to_string(), "erin".to_string()];
+        let to_record = |(name, field): (&str, &str), hidden| index::Record {
+            name: name.to_string(),
+            id: None,
+            hidden,
+            fields: vec![name.to_string(), field.to_string()],
+        };
+        apply_changes(&mut records, upserts, &HashSet::from([1]), &deletions, to_record);
+        let merged: Vec<(&str, &str, bool)> =
+            records.iter().map(|x| (x.name.as_str(), x.fields[1].as_str(), x.hidden)).collect();
+        // Upserts replace records in place, with their own hidden flag, and new records are added at the end
+        assert_eq!(
+            merged,
+            vec![("alice", "new", false), ("bob", "new", false), ("dave", "new", true)]
+        );
+    }
+
+    #[test]
+    fn applies_deletions_before_upserts() {
+        let mut records = vec![record("alice", false)];
+        let to_record = |name: &str, hidden| record(name, hidden);
+        apply_changes(&mut records, vec!["alice"], &HashSet::new(), &["alice".to_string()], to_record);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].name, "alice");
     }
 }